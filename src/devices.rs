@@ -1,10 +1,20 @@
-use crate::{Address, AddressMask};
+use crate::{Address, AddressMask, RamInit};
 
 pub trait BusDevice {
     fn read(&mut self, address: Address) -> Option<u8>;
     fn write(&mut self, address: Address, data: u8) -> bool;
 }
 
+impl<Device: BusDevice + ?Sized> BusDevice for Box<Device> {
+    fn read(&mut self, address: Address) -> Option<u8> {
+        (**self).read(address)
+    }
+
+    fn write(&mut self, address: Address, data: u8) -> bool {
+        (**self).write(address, data)
+    }
+}
+
 pub struct RamBank<const SIZE: usize> {
     map: AddressMask,
     memory: [u8; SIZE],
@@ -17,6 +27,10 @@ impl<const SIZE: usize> RamBank<SIZE> {
             memory: [0u8; SIZE],
         }
     }
+
+    pub fn fill(&mut self, ram: RamInit) {
+        ram.fill(&mut self.memory);
+    }
 }
 
 impl<const SIZE: usize> BusDevice for RamBank<SIZE> {
@@ -1,481 +1,232 @@
-use std::collections::VecDeque;
+use std::mem;
 
-use apu::Apu;
-use ppu::Ppu;
+use apu::{Apu, ApuRates, NTSC_RATES, PAL_RATES};
+use dma::{DmaCycle, DmcDma, OamDma};
+use mixer::Mixer;
+use ppu::{NametableMapper, Ppu};
 
 use crate::{
-    isa6502::{addressing::*, instructions::*, *},
+    isa6502::cpu::{Cpu6502, Variant},
     *,
 };
 
 pub mod apu;
+pub mod dma;
 pub mod mapper;
+pub mod mixer;
+pub mod ntsc;
+pub mod palette;
 pub mod ppu;
 pub mod rom;
 
-type Microcode<CPU> = (fn(&mut CPU) -> Address, BusDirection<CPU>);
-
+/// The 2A03 is an NMOS 6502 core with the decimal mode circuitry disconnected.
 #[derive(Debug)]
-pub struct RP2A03 {
-    registers: Registers,
-    decode_cache: [Option<fn(&mut Self)>; 256],
-    timing: VecDeque<Microcode<Self>>,
-    opcode: u8,
-    data_latch: u8,
-    cycles: u64,
-}
-
-impl MicrocodeControl for RP2A03 {
-    fn push_microcode(
-        &mut self,
-        address_mode: fn(&mut Self) -> Address,
-        bus_mode: BusDirection<Self>,
-    ) {
-        self.timing.push_front((address_mode, bus_mode));
-    }
-
-    fn queue_microcode(
-        &mut self,
-        address_mode: fn(&mut Self) -> Address,
-        bus_mode: BusDirection<Self>,
-    ) {
-        self.timing.push_back((address_mode, bus_mode));
-    }
-
-    fn queue_decode(&mut self) {
-        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::decode_opcode));
-    }
-
-    fn clear_microcode(&mut self) {
-        self.timing.clear();
-    }
-
-    fn queue_read<INST: ReadInstruction>(&mut self, address_mode: fn(&mut Self) -> Address) {
-        self.queue_microcode(
-            address_mode,
-            BusDirection::Read(|cpu| INST::execute(&mut cpu.registers, &cpu.data_latch)),
-        );
-    }
-
-    fn queue_read_write<INST: ReadWriteInstruction>(
-        &mut self,
-        address_mode: fn(&mut Self) -> Address,
-    ) {
-        self.queue_microcode(
-            address_mode,
-            BusDirection::Write(|cpu| INST::execute(&mut cpu.registers, &mut cpu.data_latch)),
-        );
-    }
+pub struct Ricoh2A03;
 
-    fn queue_write<INST: WriteInstruction>(&mut self, address_mode: fn(&mut Self) -> Address) {
-        self.queue_microcode(
-            address_mode,
-            BusDirection::Write(|cpu| INST::execute(&mut cpu.registers, &mut cpu.data_latch)),
-        );
+impl Variant for Ricoh2A03 {
+    fn decode(cpu: &mut Cpu6502<Self>) -> fn(&mut Cpu6502<Self>) {
+        cpu.decode_nmos::<false>()
     }
 }
 
-impl AddressMode for RP2A03 {
-    fn address(&mut self) -> Address {
-        self.registers.address_buffer
-    }
-
-    fn pc(&mut self) -> Address {
-        self.registers.pc
-    }
-
-    fn pc_inc(&mut self) -> Address {
-        let address = self.pc();
-        self.registers.pc.increment();
-        address
-    }
-
-    fn stack(&mut self) -> Address {
-        Address(0x100 | self.registers.stack as u16)
-    }
-
-    fn stack_push(&mut self) -> Address {
-        let address = self.stack();
-        self.registers.stack = self.registers.stack.wrapping_sub(1);
-        address
-    }
-
-    fn stack_pull(&mut self) -> Address {
-        self.registers.stack = self.registers.stack.wrapping_add(1);
-        self.stack()
-    }
-
-    fn vector<const VECTOR: u8>(&mut self) -> Address {
-        Address(0xFF00 | VECTOR as u16)
-    }
-
-    fn zeropage(&mut self) -> Address {
-        Address(self.registers.operand as u16)
-    }
+pub type RP2A03 = Cpu6502<Ricoh2A03>;
+
+/// Console timing, which sets the master clock dividers, the frame layout and the APU rates.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    /// 2A07 CPU and 2C07 PPU.
+    Pal,
+    /// Famiclone with PAL frame timing around an NTSC style CPU.
+    Dendy,
 }
 
-impl Decode for RP2A03 {
-    fn decode_opcode(&mut self) {
-        self.opcode = self.data_latch;
-
-        if let Some(enqueue) = self.decode_cache[self.opcode as usize] {
-            enqueue(self);
-            return;
+impl Region {
+    /// Decodes the CPU/PPU timing byte of an NES 2.0 header. Multi-region images run as NTSC.
+    pub fn from_timing(timing: u8) -> Self {
+        match timing & 0b11 {
+            1 => Region::Pal,
+            3 => Region::Dendy,
+            _ => Region::Ntsc,
         }
-
-        // 0000_0000
-        // bit 7-5: row
-        // bit 4-0: column
-        // bit 1-0: block
-        let row = (self.opcode & 0b1110_0000) >> 4;
-        let column = self.opcode & 0b0001_1111;
-        let block = self.opcode & 0b0000_0011;
-
-        let enqueue_timing: fn(&mut Self) = if self.opcode & 0x1F == 0x10 {
-            Self::queue_branch
-        } else {
-            match (row, column, block) {
-                // Control
-                (0x0, 0x0, _) => Self::queue_brk,
-                (0x2, 0x0, _) => Self::queue_jsr,
-                (0x4, 0x0, _) => Self::queue_rti,
-                (0x6, 0x0, _) => Self::queue_rts,
-                (0x2, 0x4, _) => self.decode_addressing::<BIT, Read>(row, column),
-                (0x0, 0x8, _) => self.decode_addressing::<PHP, Write>(row, column),
-                (0x2, 0x8, _) => self.decode_addressing::<PLP, Read>(row, column),
-                (0x4, 0x8, _) => self.decode_addressing::<PHA, Write>(row, column),
-                (0x6, 0x8, _) => self.decode_addressing::<PLA, Read>(row, column),
-                (0x8, 0x8, _) => self.decode_addressing::<DEY, Read>(row, column),
-                (0xA, 0x8, _) => self.decode_addressing::<TAY, Read>(row, column),
-                (0xC, 0x8, _) => self.decode_addressing::<INY, Read>(row, column),
-                (0xE, 0x8, _) => self.decode_addressing::<INX, Read>(row, column),
-                (0x2, 0xC, _) => self.decode_addressing::<BIT, Read>(row, column),
-                (0x4, 0xC, _) => Self::queue_jmp,
-                (0x6, 0xC, _) => Self::queue_indirect_jmp,
-                (0x0, 0x18, _) => self.decode_addressing::<CLC, Read>(row, column),
-                (0x2, 0x18, _) => self.decode_addressing::<SEC, Read>(row, column),
-                (0x6, 0x18, _) => self.decode_addressing::<SEI, Read>(row, column),
-                (0x8, 0x18, _) => self.decode_addressing::<TYA, Read>(row, column),
-                (0xA, 0x18, _) => self.decode_addressing::<CLV, Read>(row, column),
-                (0xC, 0x18, _) => self.decode_addressing::<CLD, Read>(row, column),
-                (0xE, 0x18, _) => self.decode_addressing::<SED, Read>(row, column),
-
-                (0x8, _, 0) => self.decode_addressing::<STY, Write>(row, column),
-                (0xA, _, 0) => self.decode_addressing::<LDY, Read>(row, column),
-                (_, 0x14, _) => self.decode_addressing::<NOP, Read>(row, column),
-                (_, 0x1C, _) => self.decode_addressing::<NOP, Read>(row, column),
-                (0xC, _, 0) => self.decode_addressing::<CPY, Read>(row, column),
-                (0xE, _, 0) => self.decode_addressing::<CPX, Read>(row, column),
-                (_, _, 0) => self.decode_addressing::<NOP, Read>(row, column),
-
-                // ALU
-                (0x0, _, 1) => self.decode_addressing::<ORA, Read>(row, column),
-                (0x2, _, 1) => self.decode_addressing::<AND, Read>(row, column),
-                (0x4, _, 1) => self.decode_addressing::<EOR, Read>(row, column),
-                (0x6, _, 1) => self.decode_addressing::<ADC<false>, Read>(row, column),
-                (0x8, _, 1) => self.decode_addressing::<STA, Write>(row, column),
-                (0xA, _, 1) => self.decode_addressing::<LDA, Read>(row, column),
-                (0xC, _, 1) => self.decode_addressing::<CMP, Read>(row, column),
-                (0xE, _, 1) => self.decode_addressing::<SBC, Read>(row, column),
-
-                // RMW
-                (0x0, _, 2) => self.decode_addressing::<ASL, ReadWrite>(row, column),
-                (0x2, _, 2) => self.decode_addressing::<ROL, ReadWrite>(row, column),
-                (0x4, _, 2) => self.decode_addressing::<LSR, ReadWrite>(row, column),
-                (0x6, _, 2) => self.decode_addressing::<ROR, ReadWrite>(row, column),
-                (0x8, 0xA, _) => self.decode_addressing::<TXA, Read>(row, column),
-                (0x8, 0x1A, _) => self.decode_addressing::<TXS, Read>(row, column),
-                (0x8, _, 2) => self.decode_addressing::<STX, Write>(row, column),
-                (0xA, 0xA, _) => self.decode_addressing::<TAX, Read>(row, column),
-                (0xA, 0x1A, _) => self.decode_addressing::<TSX, Read>(row, column),
-                (0xA, _, 2) => self.decode_addressing::<LDX, Read>(row, column),
-                (0xC, 0xA, _) => self.decode_addressing::<DEX, Read>(row, column),
-                (0xC, _, 2) => self.decode_addressing::<DEC, ReadWrite>(row, column),
-                (0xE, 0xA, _) => self.decode_addressing::<NOP, Read>(row, column),
-                (0xE, _, 2) => self.decode_addressing::<INC, ReadWrite>(row, column),
-
-                // Illegal
-                (0x0, _, 3) => self.decode_addressing::<SLO, ReadWrite>(row, column),
-                (0x2, _, 3) => self.decode_addressing::<RLA, ReadWrite>(row, column),
-                (0x4, _, 3) => self.decode_addressing::<SRE, ReadWrite>(row, column),
-                (0x6, _, 3) => self.decode_addressing::<RRA, ReadWrite>(row, column),
-                (0x8, _, 3) => self.decode_addressing::<SAX, Write>(row, column),
-                (0xA, _, 3) => self.decode_addressing::<LAX, Read>(row, column),
-                (0xC, _, 3) => self.decode_addressing::<DCP, ReadWrite>(row, column),
-                (0xE, 0xB, _) => self.decode_addressing::<SBC, Read>(row, column),
-                (0xE, _, 3) => self.decode_addressing::<ISC, ReadWrite>(row, column),
-                _ => unimplemented!("No decode for {:02X}", self.opcode),
-            }
-        };
-        enqueue_timing(self);
-        self.decode_cache[self.opcode as usize] = Some(enqueue_timing);
     }
 
-    fn decode_addressing<INST: Instruction<IO>, IO: IOMode>(
-        &mut self,
-        row: u8,
-        column: u8,
-    ) -> fn(&mut Self)
-    where
-        Immediate: AddressingMode<Self, INST, IO>,
-        IndexedIndirectX: AddressingMode<Self, INST, IO>,
-        ZeroPage: AddressingMode<Self, INST, IO>,
-        Stack: AddressingMode<Self, INST, IO>,
-        Accumulator: AddressingMode<Self, INST, IO>,
-        Absolute: AddressingMode<Self, INST, IO>,
-        IndirectIndexedY: AddressingMode<Self, INST, IO>,
-        ZeroPageIndexed<true>: AddressingMode<Self, INST, IO>,
-        ZeroPageIndexed<false>: AddressingMode<Self, INST, IO>,
-        Implied: AddressingMode<Self, INST, IO>,
-        AbsoluteIndexed<true>: AddressingMode<Self, INST, IO>,
-        AbsoluteIndexed<false>: AddressingMode<Self, INST, IO>,
-    {
-        match column {
-            0x00 | 0x02 => Self::addressing::<Immediate, INST, IO>,
-            0x01 | 0x03 => Self::addressing::<IndexedIndirectX, INST, IO>,
-            0x04..=0x07 => Self::addressing::<ZeroPage, INST, IO>,
-            0x08 => match row {
-                0x0..=0x6 => Self::addressing::<Stack, INST, IO>,
-                _ => Self::addressing::<Accumulator, INST, IO>,
-            },
-            0x0A => Self::addressing::<Accumulator, INST, IO>,
-            0x09 | 0x0B => Self::addressing::<Immediate, INST, IO>,
-            0x0C..=0x0F => Self::addressing::<Absolute, INST, IO>,
-            0x11 | 0x13 => Self::addressing::<IndirectIndexedY, INST, IO>,
-            0x14 | 0x15 => Self::addressing::<ZeroPageIndexed<true>, INST, IO>,
-            0x16 | 0x17 => match row {
-                0x8 | 0xA => Self::addressing::<ZeroPageIndexed<false>, INST, IO>,
-                _ => Self::addressing::<ZeroPageIndexed<true>, INST, IO>,
-            },
-            0x18 | 0x1A => Self::addressing::<Implied, INST, IO>,
-            0x19 | 0x1B => Self::addressing::<AbsoluteIndexed<false>, INST, IO>,
-            0x1C | 0x1D => Self::addressing::<AbsoluteIndexed<true>, INST, IO>,
-            0x1E | 0x1F => match row {
-                0x8 | 0xA => Self::addressing::<AbsoluteIndexed<false>, INST, IO>,
-                _ => Self::addressing::<AbsoluteIndexed<true>, INST, IO>,
-            },
-            _ => unreachable!("No addressing mode implemented for {:02X}", column),
+    pub const fn master_clock_rate(self) -> u64 {
+        match self {
+            Region::Ntsc => 236_250_000 / 11,
+            Region::Pal | Region::Dendy => 26_601_712,
         }
     }
 
-    fn queue_branch(&mut self) {
-        let should_branch = match self.opcode {
-            0x10 => !self.registers.p.contains(StatusFlags::N),
-            0x30 => self.registers.p.contains(StatusFlags::N),
-            0x50 => !self.registers.p.contains(StatusFlags::V),
-            0x70 => self.registers.p.contains(StatusFlags::V),
-            0x90 => !self.registers.p.contains(StatusFlags::C),
-            0xB0 => self.registers.p.contains(StatusFlags::C),
-            0xD0 => !self.registers.p.contains(StatusFlags::Z),
-            0xF0 => self.registers.p.contains(StatusFlags::Z),
-            _ => unreachable!("{:02X}", self.opcode),
-        };
-
-        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::pull_operand));
-
-        if should_branch {
-            self.queue_microcode(
-                Self::pc,
-                BusDirection::Read(|cpu| {
-                    let mut pc = cpu.registers.pc;
-                    pc.offset(cpu.registers.operand as i8);
-
-                    if cpu.registers.pc.high() != pc.high() {
-                        cpu.push_microcode(
-                            |cpu| {
-                                let mut address = cpu.registers.pc;
-                                address.offset(cpu.registers.operand as i8);
-                                address.set_high(cpu.registers.pc.high());
-                                address
-                            },
-                            BusDirection::Read(|cpu| {
-                                cpu.registers.pc.offset(cpu.registers.operand as i8)
-                            }),
-                        );
-                    } else {
-                        cpu.registers.pc = pc;
-                    }
-                }),
-            );
+    pub const fn cpu_divisor(self) -> u64 {
+        match self {
+            Region::Ntsc => 12,
+            Region::Pal => 16,
+            Region::Dendy => 15,
         }
-
-        self.queue_decode();
-    }
-
-    fn queue_brk(&mut self) {
-        todo!()
     }
 
-    fn queue_jmp(&mut self) {
-        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::pull_operand));
-        self.queue_read::<JMP>(Self::pc_inc);
-        self.queue_decode();
-    }
-
-    fn queue_indirect_jmp(&mut self) {
-        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::buffer_low));
-        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::buffer_high));
-        self.queue_microcode(Self::address, BusDirection::Read(Self::pull_operand));
-        self.queue_read::<JMP>(|cpu| cpu.address().index(1));
-        self.queue_decode();
+    pub const fn ppu_divisor(self) -> u64 {
+        match self {
+            Region::Ntsc => 4,
+            Region::Pal | Region::Dendy => 5,
+        }
     }
 
-    fn queue_jsr(&mut self) {
-        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::pull_operand));
-        self.queue_microcode(Self::stack, BusDirection::Read(Self::nop));
-        self.queue_microcode(
-            Self::stack_push,
-            BusDirection::Write(Self::write_instruction::<PCH>),
-        );
-        self.queue_microcode(
-            Self::stack_push,
-            BusDirection::Write(Self::write_instruction::<PCL>),
-        );
-        self.queue_read::<JSR>(Self::pc_inc);
-        self.queue_decode();
+    pub const fn scanlines(self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
     }
 
-    fn queue_rti(&mut self) {
-        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::nop));
-        self.queue_microcode(Self::stack, BusDirection::Read(Self::nop));
-        self.queue_read::<PLP>(Self::stack_pull);
-        self.queue_read::<PCL>(Self::stack_pull);
-        self.queue_read::<PCH>(Self::stack_pull);
-        self.queue_decode();
+    /// The Dendy keeps 51 post-render lines before vblank, so its NMI arrives 50 lines later than on PAL.
+    pub const fn vblank_scanline(self) -> u16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
     }
 
-    fn queue_rts(&mut self) {
-        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::nop));
-        self.queue_microcode(Self::stack, BusDirection::Read(Self::nop));
-        self.queue_read::<PCL>(Self::stack_pull);
-        self.queue_read::<PCH>(Self::stack_pull);
-        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::nop));
-        self.queue_decode();
+    pub const fn apu_rates(self) -> &'static ApuRates {
+        match self {
+            Region::Ntsc | Region::Dendy => &NTSC_RATES,
+            Region::Pal => &PAL_RATES,
+        }
     }
 }
 
-impl MicrocodeInstructions for RP2A03 {
-    fn pull_operand(&mut self) {
-        self.registers.operand = self.data_latch;
-    }
-
-    fn read_instruction<INST: ReadInstruction>(&mut self) {
-        INST::execute(&mut self.registers, &self.data_latch);
-    }
+pub struct SystemBus<PrgMapper: BusDevice, ChrMapper: NametableMapper> {
+    ram: RamBank<{ 2 * usize::K }>,
+    apu: Apu,
+    mixer: Option<Mixer>,
+    ppu: Ppu<ChrMapper>,
+    mapper: PrgMapper,
+    open_bus: u8,
+    oam_dma: Option<OamDma>,
+    dmc_dma: Option<DmcDma>,
+    dma_halt: bool,
+    /// Whether a DMA unit drove the bus this cycle, hiding the CPU's halted read.
+    dma_bus: bool,
+    /// Whether the CPU wrote last cycle, which keeps it from halting.
+    cpu_wrote: bool,
+    master_clock: u64,
+    cpu_divisor: u64,
+    ppu_divisor: u64,
+}
 
-    fn write_instruction<INST: WriteInstruction>(&mut self) {
-        INST::execute(&mut self.registers, &mut self.data_latch);
+impl<PrgMapper: BusDevice, ChrMapper: NametableMapper> SystemBus<PrgMapper, ChrMapper> {
+    pub fn new(prg_mapper: PrgMapper, chr_mapper: ChrMapper, region: Region) -> Self {
+        Self {
+            ram: RamBank::new(AddressMask::from_block(Address(0), 3, 2)),
+            apu: Apu::new(region.apu_rates()),
+            mixer: None,
+            ppu: Ppu::new(chr_mapper, region),
+            mapper: prg_mapper,
+            open_bus: 0,
+            oam_dma: None,
+            dmc_dma: None,
+            dma_halt: false,
+            dma_bus: false,
+            cpu_wrote: false,
+            master_clock: 0,
+            cpu_divisor: region.cpu_divisor(),
+            ppu_divisor: region.ppu_divisor(),
+        }
     }
 
-    fn borrow_accumulator(&mut self) -> &mut u8 {
-        &mut self.registers.a
+    pub fn ppu(&self) -> &Ppu<ChrMapper> {
+        &self.ppu
     }
 
-    fn index_x(&self) -> u8 {
-        self.registers.x
+    pub fn apu(&self) -> &Apu {
+        &self.apu
     }
 
-    fn index_y(&self) -> u8 {
-        self.registers.y
+    /// Audio output is off until a mixer is attached.
+    pub fn set_mixer(&mut self, mixer: Option<Mixer>) {
+        self.mixer = mixer;
     }
 
-    fn buffer_low(&mut self) {
-        self.registers.address_buffer.set_low(self.data_latch);
+    pub fn mixer_mut(&mut self) -> Option<&mut Mixer> {
+        self.mixer.as_mut()
     }
 
-    fn buffer_high(&mut self) {
-        self.registers.address_buffer.set_high(self.data_latch);
-    }
-}
+    const OAM_DMA: Address = Address(0x4014);
+    const OAM_DATA: Address = Address(0x2004);
 
-impl RP2A03 {
-    pub fn new() -> Self {
-        let mut cpu = Self {
-            registers: Registers::default(),
-            timing: VecDeque::with_capacity(8),
-            decode_cache: [None; 256],
-            opcode: 0,
-            data_latch: 0,
-            cycles: 0,
+    /// Returns whether the transfer used the bus.
+    fn run_oam_dma(&mut self, get_cycle: bool) -> bool {
+        let Some(dma) = &mut self.oam_dma else {
+            return false;
         };
-        cpu.reset();
-        cpu
-    }
-
-    fn addressing<ADDRESSING: AddressingMode<Self, INST, IO>, INST: Instruction<IO>, IO: IOMode>(
-        &mut self,
-    ) {
-        ADDRESSING::enqueue(self);
-    }
-
-    pub fn reset(&mut self) {
-        self.registers.stack = 0;
-        self.registers.p.set(StatusFlags::default(), true);
-        self.clear_microcode();
-        self.queue_read::<NOP>(Self::pc_inc);
-        self.queue_read::<NOP>(Self::pc_inc);
-        self.queue_read::<NOP>(Self::stack_push);
-        self.queue_read::<NOP>(Self::stack_push);
-        self.queue_read::<NOP>(Self::stack_push);
-        self.queue_read::<PCL>(Self::vector::<0xFC>);
-        self.queue_read::<PCH>(Self::vector::<0xFD>);
-        self.queue_decode();
-    }
-}
 
-impl Cpu for RP2A03 {
-    const CLOCK_DIVISOR: u64 = 12;
-
-    fn cycle(&mut self, bus: &mut impl Bus) {
-        self.cycles = self.cycles.wrapping_add(1);
-        if self.cycles % Self::CLOCK_DIVISOR == 0 {
-            match self.timing.pop_front().unwrap() {
-                (address_mode, BusDirection::Read(operation)) => {
-                    self.data_latch = bus.read(address_mode(self));
-                    operation(self);
+        match dma.cycle(get_cycle) {
+            DmaCycle::Idle => return false,
+            DmaCycle::Read(address) => {
+                let data = self.read(address);
+                if let Some(dma) = &mut self.oam_dma {
+                    dma.latch(data);
                 }
-                (address_mode, BusDirection::Write(operation)) => {
-                    let address = address_mode(self);
-                    operation(self);
-                    bus.write(address, self.data_latch);
+            }
+            DmaCycle::Write(data) => {
+                self.open_bus = data;
+                self.ppu.write(Self::OAM_DATA, data);
+                if self.oam_dma.as_ref().is_some_and(OamDma::is_done) {
+                    self.oam_dma = None;
                 }
             }
         }
+        true
     }
-}
 
-pub struct SystemBus<PrgMapper: BusDevice, ChrMapper: BusDevice> {
-    ram: RamBank<{ 2 * usize::K }>,
-    apu: Apu,
-    ppu: Ppu<ChrMapper>,
-    mapper: PrgMapper,
-}
+    /// Returns whether the fetch used the bus.
+    fn run_dmc_dma(&mut self, get_cycle: bool, cpu_halted: bool) -> bool {
+        let Some(dma) = &mut self.dmc_dma else {
+            return false;
+        };
 
-impl<PrgMapper: BusDevice, ChrMapper: BusDevice> SystemBus<PrgMapper, ChrMapper> {
-    pub fn new(prg_mapper: PrgMapper, chr_mapper: ChrMapper) -> Self {
-        Self {
-            ram: RamBank::new(AddressMask::from_block(Address(0), 3, 2)),
-            apu: Default::default(),
-            ppu: Ppu::new(chr_mapper),
-            mapper: prg_mapper,
+        match dma.cycle(get_cycle, cpu_halted) {
+            DmaCycle::Read(address) => {
+                let data = self.read(address);
+                self.apu.dmc_dma_complete(data);
+                self.dmc_dma = None;
+                true
+            }
+            _ => false,
         }
     }
 }
 
-impl<PrgMapper: BusDevice, ChrMapper: BusDevice> Bus for SystemBus<PrgMapper, ChrMapper> {
+impl<PrgMapper: BusDevice, ChrMapper: NametableMapper> Bus for SystemBus<PrgMapper, ChrMapper> {
     fn read(&mut self, address: Address) -> u8 {
-        self.ram.read(address).unwrap_or_else(move || {
-            self.ppu.read(address).unwrap_or_else(move || {
-                self.apu.read(address).unwrap_or_else(move || {
-                    self.mapper
-                        .read(address)
-                        .unwrap_or_else(|| panic!("No device for read:{:?}", address))
-                })
+        // Unmapped reads return whatever was last driven on the data bus
+        let open_bus = self.open_bus;
+        if address == Apu::STATUS {
+            // The APU status is read inside the 2A03, so it never reaches the external data bus
+            let status = self.apu.read(address).unwrap_or_default();
+            return status | open_bus & Apu::STATUS_OPEN_BUS;
+        }
+
+        self.open_bus = self.ram.read(address).unwrap_or_else(|| {
+            self.ppu.read(address).unwrap_or_else(|| {
+                self.apu
+                    .read(address)
+                    .unwrap_or_else(|| self.mapper.read(address).unwrap_or(open_bus))
             })
-        })
+        });
+        self.open_bus
     }
 
     fn write(&mut self, address: Address, data: u8) {
+        self.open_bus = data;
+        self.cpu_wrote = true;
+        if address == Self::OAM_DMA {
+            self.oam_dma = Some(OamDma::new(data));
+            return;
+        }
+
         if [
             self.ram.write(address, data),
             self.ppu.write(address, data),
@@ -488,9 +239,71 @@ impl<PrgMapper: BusDevice, ChrMapper: BusDevice> Bus for SystemBus<PrgMapper, Ch
             println!("No device for write:{:?}", address);
         }
     }
+
+    fn power_on(&mut self, ram: RamInit) {
+        self.ram.fill(ram);
+        self.ppu.power_on();
+        self.apu.power_on();
+        self.open_bus = 0;
+    }
+
+    fn reset(&mut self) {
+        self.ppu.reset();
+        self.apu.reset();
+        self.oam_dma = None;
+        self.dmc_dma = None;
+    }
+
+    /// Repeated reads reach the devices, so a DMA halt during a $2007 or controller read reads it several times.
+    fn halted_read(&mut self, address: Address) {
+        if !self.dma_bus {
+            self.read(address);
+        }
+    }
+
+    fn clock_pulse(&mut self) -> bool {
+        // Both dividers start together, so the first PPU dot of every CPU cycle lands on the same master cycle
+        let phase = self.master_clock;
+        self.master_clock = self.master_clock.wrapping_add(1);
+
+        if phase.is_multiple_of(self.ppu_divisor) {
+            self.ppu.tick();
+        }
+
+        let cpu_cycle = phase.is_multiple_of(self.cpu_divisor);
+        if cpu_cycle {
+            // The CPU stays halted through the cycle that finishes a transfer
+            self.dma_halt = self.oam_dma.is_some() || self.dmc_dma.is_some();
+            let get_cycle = (phase / self.cpu_divisor).is_multiple_of(2);
+            let cpu_halted = !mem::take(&mut self.cpu_wrote);
+
+            // A DMC fetch takes the bus first, pushing sprite DMA back a get/put pair
+            self.dma_bus = self.run_dmc_dma(get_cycle, cpu_halted) || self.run_oam_dma(get_cycle);
+
+            self.apu.tick();
+            if let Some(mixer) = &mut self.mixer {
+                mixer.clock(&self.apu);
+            }
+            if self.dmc_dma.is_none() {
+                if let Some(address) = self.apu.dmc_dma_request() {
+                    self.dmc_dma = Some(DmcDma::new(address));
+                    self.dma_halt = true;
+                }
+            }
+        }
+        cpu_cycle
+    }
+
+    fn signals(&self) -> Signals {
+        Signals {
+            nmi: self.ppu.nmi(),
+            irq: self.apu.irq(),
+            dma_halt: self.dma_halt,
+        }
+    }
 }
 
-impl<PrgMapper: fmt::Debug + BusDevice, ChrMapper: BusDevice> fmt::Debug
+impl<PrgMapper: fmt::Debug + BusDevice, ChrMapper: NametableMapper> fmt::Debug
     for SystemBus<PrgMapper, ChrMapper>
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -516,7 +329,7 @@ impl<BUS: Bus> NesLogger for System<RP2A03, BUS> {
             y: self.cpu.registers.y,
             p: self.cpu.registers.p.bits(),
             stack: self.cpu.registers.stack,
-            cycles: self.cpu.cycles / RP2A03::CLOCK_DIVISOR,
+            cycles: self.cpu.cycles,
         }
     }
 }
@@ -1,21 +1,833 @@
 use crate::{devices::BusDevice, Address, AddressMask};
 
+/// Timer periods that differ between the NTSC and PAL APUs, all in CPU cycles.
+#[derive(Debug)]
+pub struct ApuRates {
+    pub noise_periods: [u16; 16],
+    pub dmc_periods: [u16; 16],
+    /// Quarter and half frame clocks, with the last entry only used by the 5-step sequence.
+    pub frame_steps: [u32; 5],
+}
+
+pub const NTSC_RATES: ApuRates = ApuRates {
+    noise_periods: [
+        4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+    ],
+    dmc_periods: [
+        428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+    ],
+    frame_steps: [7457, 14913, 22371, 29829, 37281],
+};
+
+pub const PAL_RATES: ApuRates = ApuRates {
+    noise_periods: [
+        4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+    ],
+    dmc_periods: [
+        398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+    ],
+    frame_steps: [8313, 16627, 24939, 33253, 41565],
+};
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+const DUTY_SEQUENCES: [u8; 4] = [0b0100_0000, 0b0110_0000, 0b0111_1000, 0b1001_1111];
+
+#[derive(Default)]
+struct Envelope {
+    start: bool,
+    looping: bool,
+    constant_volume: bool,
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    fn write(&mut self, data: u8) {
+        self.looping = data & 0x20 != 0;
+        self.constant_volume = data & 0x10 != 0;
+        self.volume = data & 0x0F;
+    }
+
+    fn restart(&mut self) {
+        self.start = true;
+    }
+
+    /// Clocked by the quarter frame.
+    fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.constant_volume {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+}
+
+#[derive(Default)]
+struct LengthCounter {
+    enabled: bool,
+    halted: bool,
+    count: u8,
+    /// Set when the current cycle's half frame clock counted down, which drops a reload in the same cycle.
+    decremented: bool,
+}
+
+impl LengthCounter {
+    fn load(&mut self, data: u8) {
+        if self.enabled && !self.decremented {
+            self.count = LENGTH_TABLE[(data >> 3) as usize];
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.count = 0;
+        }
+    }
+
+    /// Clocked by the half frame.
+    fn clock(&mut self) {
+        if !self.halted && self.count > 0 {
+            self.count -= 1;
+            self.decremented = true;
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.count > 0
+    }
+}
+
 #[derive(Default)]
-pub struct Apu {}
+struct Sweep {
+    enabled: bool,
+    period: u8,
+    negate: bool,
+    shift: u8,
+    divider: u8,
+    reload: bool,
+}
+
+impl Sweep {
+    fn write(&mut self, data: u8) {
+        self.enabled = data & 0x80 != 0;
+        self.period = data >> 4 & 0x07;
+        self.negate = data & 0x08 != 0;
+        self.shift = data & 0x07;
+        self.reload = true;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PulseChannel {
+    /// Negates the sweep change with ones' complement, so it sweeps down one further than pulse 2.
+    One,
+    /// Negates the sweep change with two's complement.
+    Two,
+}
+
+/// A square wave channel, $4000-$4003 or $4004-$4007.
+pub struct Pulse {
+    channel: PulseChannel,
+    duty: u8,
+    step: u8,
+    timer_period: u16,
+    timer: u16,
+    envelope: Envelope,
+    length: LengthCounter,
+    sweep: Sweep,
+}
+
+impl Pulse {
+    pub fn new(channel: PulseChannel) -> Self {
+        Self {
+            channel,
+            duty: 0,
+            step: 0,
+            timer_period: 0,
+            timer: 0,
+            envelope: Default::default(),
+            length: Default::default(),
+            sweep: Default::default(),
+        }
+    }
+
+    pub fn write(&mut self, register: u8, data: u8) {
+        match register & 0b11 {
+            0 => {
+                self.duty = data >> 6;
+                self.length.halted = data & 0x20 != 0;
+                self.envelope.write(data);
+            }
+            1 => self.sweep.write(data),
+            2 => self.timer_period = self.timer_period & 0x700 | data as u16,
+            _ => {
+                self.timer_period = self.timer_period & 0xFF | (data as u16 & 0x07) << 8;
+                self.length.load(data);
+                self.step = 0;
+                self.envelope.restart();
+            }
+        }
+    }
+
+    pub fn timer_period(&self) -> u16 {
+        self.timer_period
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length.set_enabled(enabled);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length.is_active()
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change = self.timer_period >> self.sweep.shift;
+        match (self.sweep.negate, self.channel) {
+            (false, _) => self.timer_period + change,
+            (true, PulseChannel::One) => self.timer_period.saturating_sub(change + 1),
+            (true, PulseChannel::Two) => self.timer_period.saturating_sub(change),
+        }
+    }
+
+    /// The sweep unit silences the channel whether or not it's enabled.
+    fn is_muted(&self) -> bool {
+        self.timer_period < 8 || self.sweep_target() > 0x7FF
+    }
+
+    /// Clocked every APU cycle, which is every other CPU cycle.
+    pub fn tick(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.step = (self.step + 1) & 0b111;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn half_frame(&mut self) {
+        self.length.clock();
+
+        if self.sweep.divider == 0 && self.sweep.enabled && self.sweep.shift > 0 && !self.is_muted()
+        {
+            self.timer_period = self.sweep_target();
+        }
+
+        if self.sweep.divider == 0 || self.sweep.reload {
+            self.sweep.divider = self.sweep.period;
+            self.sweep.reload = false;
+        } else {
+            self.sweep.divider -= 1;
+        }
+    }
+
+    /// The channel's current 4-bit output level.
+    pub fn output(&self) -> u8 {
+        let high = DUTY_SEQUENCES[self.duty as usize] << self.step & 0x80 != 0;
+        if high && self.length.is_active() && !self.is_muted() {
+            self.envelope.output()
+        } else {
+            0
+        }
+    }
+}
+
+const TRIANGLE_SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+/// The triangle wave channel, $4008-$400B.
+#[derive(Default)]
+pub struct Triangle {
+    step: u8,
+    timer_period: u16,
+    timer: u16,
+    control: bool,
+    linear_reload: bool,
+    linear_period: u8,
+    linear_counter: u8,
+    length: LengthCounter,
+}
+
+impl Triangle {
+    pub fn write(&mut self, register: u8, data: u8) {
+        match register & 0b11 {
+            0 => {
+                self.control = data & 0x80 != 0;
+                self.length.halted = self.control;
+                self.linear_period = data & 0x7F;
+            }
+            1 => {}
+            2 => self.timer_period = self.timer_period & 0x700 | data as u16,
+            _ => {
+                self.timer_period = self.timer_period & 0xFF | (data as u16 & 0x07) << 8;
+                self.length.load(data);
+                self.linear_reload = true;
+            }
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length.set_enabled(enabled);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length.is_active()
+    }
+
+    /// Clocked every CPU cycle. The sequencer holds its step while either counter is zero.
+    pub fn tick(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.linear_counter > 0 && self.length.is_active() {
+                self.step = (self.step + 1) & 0x1F;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn quarter_frame(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_period;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    pub fn half_frame(&mut self) {
+        self.length.clock();
+    }
+
+    /// Silencing the triangle freezes it mid-sequence rather than dropping it to zero.
+    pub fn output(&self) -> u8 {
+        TRIANGLE_SEQUENCE[self.step as usize]
+    }
+}
+
+/// The pseudo-random noise channel, $400C-$400F.
+pub struct Noise {
+    periods: &'static [u16; 16],
+    short_mode: bool,
+    timer_period: u16,
+    timer: u16,
+    shift_register: u16,
+    envelope: Envelope,
+    length: LengthCounter,
+}
+
+impl Noise {
+    pub fn new(rates: &'static ApuRates) -> Self {
+        Self {
+            periods: &rates.noise_periods,
+            short_mode: false,
+            timer_period: rates.noise_periods[0] - 1,
+            timer: 0,
+            shift_register: 1,
+            envelope: Default::default(),
+            length: Default::default(),
+        }
+    }
+
+    pub fn write(&mut self, register: u8, data: u8) {
+        match register & 0b11 {
+            0 => {
+                self.length.halted = data & 0x20 != 0;
+                self.envelope.write(data);
+            }
+            1 => {}
+            2 => {
+                self.short_mode = data & 0x80 != 0;
+                self.timer_period = self.periods[(data & 0x0F) as usize] - 1;
+            }
+            _ => {
+                self.length.load(data);
+                self.envelope.restart();
+            }
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length.set_enabled(enabled);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length.is_active()
+    }
+
+    /// Clocked every CPU cycle, as the period table is in CPU cycles.
+    pub fn tick(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            // Short mode taps bit 6 instead of bit 1, giving a 93 step sequence instead of 32767
+            let tap = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift_register ^ self.shift_register >> tap) & 1;
+            self.shift_register = self.shift_register >> 1 | feedback << 14;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn half_frame(&mut self) {
+        self.length.clock();
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.shift_register & 1 == 0 && self.length.is_active() {
+            self.envelope.output()
+        } else {
+            0
+        }
+    }
+}
+
+/// The delta modulation channel, $4010-$4013, which plays 1-bit delta samples fetched from CPU memory by DMA.
+pub struct Dmc {
+    periods: &'static [u16; 16],
+    irq_enabled: bool,
+    looping: bool,
+    timer_period: u16,
+    timer: u16,
+    level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    address: u16,
+    bytes_remaining: u16,
+    buffer: Option<u8>,
+    fetching: bool,
+    /// Cycles until the first fetch after the channel is enabled with an empty buffer.
+    start_delay: u8,
+    shift_register: u8,
+    bits_remaining: u8,
+    silenced: bool,
+    interrupt: bool,
+}
+
+impl Dmc {
+    pub fn new(rates: &'static ApuRates) -> Self {
+        Self {
+            periods: &rates.dmc_periods,
+            irq_enabled: false,
+            looping: false,
+            timer_period: rates.dmc_periods[0] - 1,
+            timer: 0,
+            level: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            address: 0xC000,
+            bytes_remaining: 0,
+            buffer: None,
+            fetching: false,
+            start_delay: 0,
+            shift_register: 0,
+            bits_remaining: 8,
+            silenced: true,
+            interrupt: false,
+        }
+    }
+
+    pub fn write(&mut self, register: u8, data: u8) {
+        match register & 0b11 {
+            0 => {
+                self.irq_enabled = data & 0x80 != 0;
+                if !self.irq_enabled {
+                    self.interrupt = false;
+                }
+                self.looping = data & 0x40 != 0;
+                self.timer_period = self.periods[(data & 0x0F) as usize] - 1;
+            }
+            1 => self.level = data & 0x7F,
+            2 => self.sample_address = 0xC000 | (data as u16) << 6,
+            _ => self.sample_length = (data as u16) << 4 | 1,
+        }
+    }
+
+    /// Enabling only restarts a finished sample, and waits 2 or 3 cycles for the first fetch depending on parity.
+    fn set_enabled(&mut self, enabled: bool, get_cycle: bool) {
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+            if self.buffer.is_none() {
+                self.start_delay = if get_cycle { 2 } else { 3 };
+            }
+        }
+    }
+
+    fn restart(&mut self) {
+        self.address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    /// The address of the next sample byte, once, when the buffer has emptied and there's sample left to fetch.
+    fn dma_request(&mut self) -> Option<Address> {
+        if self.fetching || self.start_delay > 0 || self.buffer.is_some() || !self.is_active() {
+            return None;
+        }
+
+        self.fetching = true;
+        Some(Address(self.address))
+    }
+
+    /// Sample addresses wrap around from $FFFF to $8000.
+    fn dma_complete(&mut self, data: u8) {
+        self.fetching = false;
+        self.buffer = Some(data);
+        self.address = self.address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.interrupt = true;
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle, as the period table is in CPU cycles.
+    pub fn tick(&mut self) {
+        self.start_delay = self.start_delay.saturating_sub(1);
+
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period;
+
+        if !self.silenced {
+            if self.shift_register & 1 != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.buffer.take() {
+                Some(data) => {
+                    self.silenced = false;
+                    self.shift_register = data;
+                }
+                None => self.silenced = true,
+            }
+        }
+    }
+
+    /// The 7-bit output level.
+    pub fn output(&self) -> u8 {
+        self.level
+    }
+}
+
+/// Clocks the frame counter sends to the channels on a given cycle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FrameClock {
+    Idle,
+    /// Envelopes and the triangle's linear counter.
+    Quarter,
+    /// A quarter frame plus the length counters and sweeps.
+    Half,
+}
+
+/// The $4017 sequencer.
+struct FrameCounter {
+    steps: &'static [u32; 5],
+    five_step: bool,
+    irq_inhibit: bool,
+    cycle: u32,
+    /// A mode written to $4017 and the cycles left before it restarts the sequence.
+    pending: Option<(bool, u8)>,
+}
+
+impl FrameCounter {
+    fn new(steps: &'static [u32; 5]) -> Self {
+        Self {
+            steps,
+            five_step: false,
+            irq_inhibit: false,
+            cycle: 0,
+            pending: None,
+        }
+    }
+
+    /// The new mode lands 3 cycles after a write on a get cycle, and 4 after one on a put cycle.
+    fn write(&mut self, data: u8, get_cycle: bool) {
+        self.irq_inhibit = data & 0x40 != 0;
+        self.pending = Some((data & 0x80 != 0, if get_cycle { 3 } else { 4 }));
+    }
+
+    /// Advances one CPU cycle, returning the channel clocks and whether the frame interrupt is being raised.
+    fn tick(&mut self) -> (FrameClock, bool) {
+        if let Some((five_step, delay)) = self.pending {
+            if delay > 1 {
+                self.pending = Some((five_step, delay - 1));
+            } else {
+                // Switching to the 5-step sequence clocks everything straight away
+                self.pending = None;
+                self.five_step = five_step;
+                self.cycle = 0;
+                let clock = if five_step {
+                    FrameClock::Half
+                } else {
+                    FrameClock::Idle
+                };
+                return (clock, false);
+            }
+        }
+
+        self.cycle += 1;
+        let [quarter, half, three_quarter, frame, long_frame] = *self.steps;
+        match (self.five_step, self.cycle) {
+            (_, cycle) if cycle == quarter || cycle == three_quarter => {
+                (FrameClock::Quarter, false)
+            }
+            (_, cycle) if cycle == half => (FrameClock::Half, false),
+            // The interrupt is raised for three cycles around the end of the 4-step sequence
+            (false, cycle) if cycle == frame - 1 => (FrameClock::Idle, true),
+            (false, cycle) if cycle == frame => (FrameClock::Half, true),
+            (false, cycle) if cycle == frame + 1 => {
+                self.cycle = 0;
+                (FrameClock::Idle, true)
+            }
+            (true, cycle) if cycle == long_frame => (FrameClock::Half, false),
+            (true, cycle) if cycle == long_frame + 1 => {
+                self.cycle = 0;
+                (FrameClock::Idle, false)
+            }
+            _ => (FrameClock::Idle, false),
+        }
+    }
+}
+
+pub struct Apu {
+    rates: &'static ApuRates,
+    cycles: u64,
+    pulse: [Pulse; 2],
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    frame_counter: FrameCounter,
+    frame_interrupt: bool,
+}
+
 impl Apu {
     const ADDRESS_MASK: AddressMask = AddressMask::from_block(Address(0x4000), 11, 0);
+    pub const STATUS: Address = Address(0x4015);
+    /// $4015 doesn't drive bit 5, so it reads back whatever was left on the data bus.
+    pub const STATUS_OPEN_BUS: u8 = 0x20;
+    const FRAME_COUNTER: u8 = 0x17;
+
+    /// Powers up as if $4017 had just been written with $00.
+    pub fn new(rates: &'static ApuRates) -> Self {
+        let mut frame_counter = FrameCounter::new(&rates.frame_steps);
+        frame_counter.write(0x00, true);
+        Self {
+            rates,
+            cycles: 0,
+            pulse: [Pulse::new(PulseChannel::One), Pulse::new(PulseChannel::Two)],
+            triangle: Default::default(),
+            noise: Noise::new(rates),
+            dmc: Dmc::new(rates),
+            frame_counter,
+            frame_interrupt: false,
+        }
+    }
+
+    pub fn power_on(&mut self) {
+        *self = Self::new(self.rates);
+    }
+
+    /// Silences every channel as a $4015 write of $00 would, clearing all four length counters, and rewrites the
+    /// last frame counter mode. The DMC keeps the low bit of its level.
+    pub fn reset(&mut self) {
+        self.write_status(0x00);
+        self.dmc.level &= 1;
+        self.frame_interrupt = false;
+        self.frame_counter
+            .write((self.frame_counter.five_step as u8) << 7, true);
+    }
+
+    /// The CPU IRQ line, held while either interrupt flag is set.
+    pub fn irq(&self) -> bool {
+        self.frame_interrupt || self.dmc.interrupt
+    }
+
+    /// Takes the DMC's request for its next sample byte, which [`Apu::dmc_dma_complete`] must answer.
+    pub fn dmc_dma_request(&mut self) -> Option<Address> {
+        self.dmc.dma_request()
+    }
+
+    pub fn dmc_dma_complete(&mut self, data: u8) {
+        self.dmc.dma_complete(data);
+    }
+
+    /// Advances the APU by one CPU cycle.
+    pub fn tick(&mut self) {
+        self.cycles = self.cycles.wrapping_add(1);
+
+        let [pulse1, pulse2] = &mut self.pulse;
+        for length in [
+            &mut pulse1.length,
+            &mut pulse2.length,
+            &mut self.triangle.length,
+            &mut self.noise.length,
+        ] {
+            length.decremented = false;
+        }
+
+        let (clock, interrupt) = self.frame_counter.tick();
+        if interrupt && !self.frame_counter.irq_inhibit {
+            self.frame_interrupt = true;
+        }
+        match clock {
+            FrameClock::Idle => {}
+            FrameClock::Quarter => self.quarter_frame(),
+            FrameClock::Half => {
+                self.quarter_frame();
+                self.half_frame();
+            }
+        }
+
+        if self.cycles.is_multiple_of(2) {
+            self.pulse.iter_mut().for_each(Pulse::tick);
+        }
+        self.triangle.tick();
+        self.noise.tick();
+        self.dmc.tick();
+    }
+
+    pub fn pulse(&self, channel: PulseChannel) -> &Pulse {
+        &self.pulse[channel as usize]
+    }
+
+    pub fn triangle(&self) -> &Triangle {
+        &self.triangle
+    }
+
+    pub fn noise(&self) -> &Noise {
+        &self.noise
+    }
+
+    pub fn dmc(&self) -> &Dmc {
+        &self.dmc
+    }
+
+    /// Envelope and linear counter clock from the frame counter.
+    pub fn quarter_frame(&mut self) {
+        self.pulse.iter_mut().for_each(Pulse::quarter_frame);
+        self.triangle.quarter_frame();
+        self.noise.quarter_frame();
+    }
+
+    /// Length counter and sweep clock from the frame counter.
+    pub fn half_frame(&mut self) {
+        self.pulse.iter_mut().for_each(Pulse::half_frame);
+        self.triangle.half_frame();
+        self.noise.half_frame();
+    }
+
+    /// Reading the status acknowledges the frame interrupt, but not the DMC one.
+    fn read_status(&mut self) -> u8 {
+        let status = [
+            self.pulse[0].is_active(),
+            self.pulse[1].is_active(),
+            self.triangle.is_active(),
+            self.noise.is_active(),
+            self.dmc.is_active(),
+            false,
+            self.frame_interrupt,
+            self.dmc.interrupt,
+        ]
+        .iter()
+        .rev()
+        .fold(0, |status, &bit| status << 1 | bit as u8);
+        self.frame_interrupt = false;
+        status
+    }
+
+    fn write_status(&mut self, data: u8) {
+        self.pulse[0].set_enabled(data & 0x01 != 0);
+        self.pulse[1].set_enabled(data & 0x02 != 0);
+        self.triangle.set_enabled(data & 0x04 != 0);
+        self.noise.set_enabled(data & 0x08 != 0);
+        self.dmc
+            .set_enabled(data & 0x10 != 0, !self.cycles.is_multiple_of(2));
+        self.dmc.interrupt = false;
+    }
 }
+
 impl BusDevice for Apu {
+    /// Only $4015 is readable, every other register is open bus.
     fn read(&mut self, address: Address) -> Option<u8> {
-        Self::ADDRESS_MASK
-            .remap(address)
-            .map(|register| register.0 as u8)
+        (address == Self::STATUS).then(|| self.read_status())
     }
 
-    fn write(&mut self, address: Address, _data: u8) -> bool {
-        match Self::ADDRESS_MASK.remap(address) {
-            Some(_) => true,
-            None => false,
+    fn write(&mut self, address: Address, data: u8) -> bool {
+        let Some(register) = Self::ADDRESS_MASK.remap(address) else {
+            return false;
+        };
+
+        match register.0 as u8 {
+            register @ 0x00..=0x07 => self.pulse[register as usize / 4].write(register, data),
+            register @ 0x08..=0x0B => self.triangle.write(register, data),
+            register @ 0x0C..=0x0F => self.noise.write(register, data),
+            register @ 0x10..=0x13 => self.dmc.write(register, data),
+            0x15 => self.write_status(data),
+            Self::FRAME_COUNTER => {
+                if data & 0x40 != 0 {
+                    self.frame_interrupt = false;
+                }
+                self.frame_counter
+                    .write(data, !self.cycles.is_multiple_of(2));
+            }
+            _ => {}
         }
+        true
     }
 }
@@ -0,0 +1,84 @@
+use crate::Address;
+
+/// The sprite DMA unit behind $4014, which copies a page of CPU memory to OAMDATA while the CPU is halted.
+pub struct OamDma {
+    page: u8,
+    index: u16,
+    halted: bool,
+    data: Option<u8>,
+}
+
+/// What the DMA unit does with the bus on one CPU cycle.
+pub enum DmaCycle {
+    Idle,
+    Read(Address),
+    Write(u8),
+}
+
+impl OamDma {
+    pub fn new(page: u8) -> Self {
+        Self {
+            page,
+            index: 0,
+            halted: false,
+            data: None,
+        }
+    }
+
+    /// The first cycle waits for the CPU to halt, then reads happen on get cycles and writes on put cycles, which
+    /// costs an extra alignment cycle when the transfer starts on a put cycle.
+    pub fn cycle(&mut self, get_cycle: bool) -> DmaCycle {
+        if !self.halted {
+            self.halted = true;
+            return DmaCycle::Idle;
+        }
+
+        match (get_cycle, self.data.take()) {
+            (true, None) => DmaCycle::Read(Address((self.page as u16) << 8 | self.index)),
+            (false, Some(data)) => {
+                self.index += 1;
+                DmaCycle::Write(data)
+            }
+            (_, data) => {
+                self.data = data;
+                DmaCycle::Idle
+            }
+        }
+    }
+
+    pub fn latch(&mut self, data: u8) {
+        self.data = Some(data);
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.index == 256
+    }
+}
+
+/// The DMC's sample DMA unit, which fetches one byte for the DMC while the CPU is halted.
+pub struct DmcDma {
+    address: Address,
+    halted: bool,
+}
+
+impl DmcDma {
+    pub fn new(address: Address) -> Self {
+        Self {
+            address,
+            halted: false,
+        }
+    }
+
+    /// Waits for the CPU to halt on a read, which it can't do while writing, then spends a dummy cycle and reads
+    /// on the next get cycle. That steals 3 or 4 cycles from a CPU that halts straight away.
+    pub fn cycle(&mut self, get_cycle: bool, cpu_halted: bool) -> DmaCycle {
+        match (self.halted, get_cycle) {
+            (false, _) => {
+                self.halted = cpu_halted;
+                DmaCycle::Idle
+            }
+            (true, true) => DmaCycle::Read(self.address),
+            (true, false) => DmaCycle::Idle,
+        }
+    }
+}
@@ -1,12 +1,61 @@
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc,
+};
+
 use crate::{Address, AddressMask, BusDevice};
 
-use super::rom::{NametableLayout, RomImage};
+use super::{
+    ppu::{NametableArrangement, NametableMapper},
+    rom::{RomError, RomImage},
+};
 use crate::ByteUnits as _;
 
-pub fn mapper_from(rom_image: &RomImage) -> (impl BusDevice, impl BusDevice) {
+pub type PrgMapper = Box<dyn BusDevice + Send>;
+pub type ChrMapper = Box<dyn NametableMapper + Send>;
+
+pub fn mapper_from(rom_image: &RomImage) -> Result<(PrgMapper, ChrMapper), RomError> {
     match rom_image.mapper {
-        0 => (NromPrgMapper::new(rom_image), NromChrMapper::new(rom_image)),
-        _ => unimplemented!(),
+        0 => Ok((
+            Box::new(NromPrgMapper::new(rom_image)?),
+            Box::new(NromChrMapper::new(rom_image)?),
+        )),
+        7 => {
+            let nametables = NametableSwitch::new(NametableArrangement::SingleScreenA);
+            Ok((
+                Box::new(AxromPrgMapper::new(rom_image, nametables.clone())?),
+                Box::new(AxromChrMapper::new(rom_image, nametables)?),
+            ))
+        }
+        mapper => Err(RomError::UnsupportedMapper(mapper)),
+    }
+}
+
+/// A nametable arrangement shared between the halves of a mapper, so register writes on the CPU side can switch
+/// the mirroring the PPU side reports.
+#[derive(Clone, Debug)]
+pub struct NametableSwitch(Arc<AtomicU8>);
+
+impl NametableSwitch {
+    pub fn new(arrangement: NametableArrangement) -> Self {
+        let switch = Self(Arc::new(AtomicU8::new(0)));
+        switch.set(arrangement);
+        switch
+    }
+
+    pub fn set(&self, arrangement: NametableArrangement) {
+        let packed = arrangement
+            .pages()
+            .iter()
+            .rev()
+            .fold(0, |packed, page| packed << 2 | page & 0b11);
+        self.0.store(packed, Ordering::Relaxed);
+    }
+
+    /// Always the `Mapped` form of whatever was set.
+    pub fn get(&self) -> NametableArrangement {
+        let packed = self.0.load(Ordering::Relaxed);
+        NametableArrangement::Mapped([0, 1, 2, 3].map(|quadrant| packed >> (quadrant * 2) & 0b11))
     }
 }
 
@@ -15,44 +64,57 @@ pub struct NromPrgMapper {
     prg_ram: Vec<u8>,
     prg_rom_map: AddressMask,
     prg_rom: Vec<u8>,
-    nametable_layout: NametableLayout,
 }
 
 impl NromPrgMapper {
-    pub fn new(rom_image: &RomImage) -> Self {
-        if rom_image.prg_ram_size > 0 {
-            unimplemented!("No PRG RAM support currently");
-        }
+    /// Maps PRG RAM at $6000 when the image asks for any or has a trainer.
+    pub fn new(rom_image: &RomImage) -> Result<Self, RomError> {
+        let has_ram =
+            rom_image.prg_ram_size + rom_image.prg_nvram_size > 0 || rom_image.trainer.is_some();
+        Self::with_ram(rom_image, has_ram)
+    }
 
-        let mirror_bits = if rom_image.prg_rom.len() > 16.KiB() {
-            0
-        } else {
-            1
-        };
+    /// Always maps 8KiB of PRG RAM at $6000, as used by test ROMs that report through it.
+    pub fn new_with_ram(rom_image: &RomImage) -> Result<Self, RomError> {
+        Self::with_ram(rom_image, true)
+    }
 
-        Self {
-            prg_ram_map: None,
-            prg_ram: vec![],
-            prg_rom_map: AddressMask::from_block(Address(0x8000), 1, mirror_bits),
-            prg_rom: rom_image.prg_rom.clone(),
-            nametable_layout: rom_image.nametable_layout,
+    fn with_ram(rom_image: &RomImage, has_ram: bool) -> Result<Self, RomError> {
+        if rom_image.submapper != 0 {
+            return Err(RomError::UnsupportedSubmapper {
+                mapper: rom_image.mapper,
+                submapper: rom_image.submapper,
+            });
         }
-    }
 
-    pub fn new_with_ram(rom_image: &RomImage) -> Self {
-        let mirror_bits = if rom_image.prg_rom.len() > 16.KiB() {
-            0
-        } else {
-            1
+        if rom_image.prg_ram_size + rom_image.prg_nvram_size > 8.KiB() {
+            return Err(RomError::InconsistentHeader(
+                "NROM has at most 8KiB of PRG RAM",
+            ));
+        }
+
+        let mirror_bits = match rom_image.prg_rom.len() {
+            0x4000 => 1,
+            0x8000 => 0,
+            _ => {
+                return Err(RomError::InconsistentHeader(
+                    "NROM PRG ROM must be 16KiB or 32KiB",
+                ))
+            }
         };
 
-        Self {
-            prg_ram_map: Some(AddressMask::from_block(Address(0x6000), 3, 0)),
-            prg_ram: vec![0u8; 8.KiB()],
+        let mut prg_ram = if has_ram { vec![0u8; 8.KiB()] } else { vec![] };
+        if let (true, Some(trainer)) = (has_ram, &rom_image.trainer) {
+            // $7000-$71FF
+            prg_ram[0x1000..0x1200].copy_from_slice(trainer);
+        }
+
+        Ok(Self {
+            prg_ram_map: has_ram.then(|| AddressMask::from_block(Address(0x6000), 3, 0)),
+            prg_ram,
             prg_rom_map: AddressMask::from_block(Address(0x8000), 1, mirror_bits),
             prg_rom: rom_image.prg_rom.clone(),
-            nametable_layout: rom_image.nametable_layout,
-        }
+        })
     }
 }
 
@@ -85,23 +147,37 @@ impl BusDevice for NromPrgMapper {
 pub struct NromChrMapper {
     chr_rom: [u8; 8 * usize::K],
     chr_rom_mask: AddressMask,
+    chr_ram: bool,
+    nametables: NametableArrangement,
+    four_screen_vram: Option<Box<[u8; 2 * usize::K]>>,
 }
 
 impl NromChrMapper {
-    pub fn new(rom_image: &RomImage) -> Self {
-        assert_eq!(
-            rom_image.chr_rom.len(),
-            8.KiB(),
-            "NROM CHR ROM must be 8KiB"
-        );
-        Self {
-            chr_rom: rom_image
+    /// Images without CHR ROM get 8KiB of CHR RAM instead.
+    pub fn new(rom_image: &RomImage) -> Result<Self, RomError> {
+        let chr_ram = rom_image.chr_rom.is_empty();
+        let chr_rom = if chr_ram {
+            [0u8; 8 * usize::K]
+        } else {
+            rom_image
                 .chr_rom
-                .clone()
+                .as_slice()
                 .try_into()
-                .expect("CHR is 8KiB for NROM"),
+                .map_err(|_| RomError::InconsistentHeader("NROM CHR ROM must be 8KiB"))?
+        };
+
+        let four_screen = rom_image.alternative_nametables;
+        Ok(Self {
+            chr_rom,
             chr_rom_mask: AddressMask::from_block(Address(0), 3, 0),
-        }
+            chr_ram,
+            nametables: if four_screen {
+                NametableArrangement::FourScreen
+            } else {
+                rom_image.nametable_layout.into()
+            },
+            four_screen_vram: four_screen.then(|| Box::new([0; 2 * usize::K])),
+        })
     }
 }
 
@@ -114,7 +190,117 @@ impl BusDevice for NromChrMapper {
     }
 
     #[inline]
-    fn write(&mut self, address: crate::Address, _: u8) -> bool {
-        self.chr_rom_mask.remap(address).is_some()
+    fn write(&mut self, address: crate::Address, data: u8) -> bool {
+        if let Some(chr_address) = self.chr_rom_mask.remap(address) {
+            if self.chr_ram {
+                self.chr_rom[chr_address] = data;
+            }
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl NametableMapper for NromChrMapper {
+    fn nametable_arrangement(&self) -> NametableArrangement {
+        self.nametables
+    }
+
+    fn cartridge_vram(&mut self) -> Option<&mut [u8; 2 * usize::K]> {
+        self.four_screen_vram.as_deref_mut()
+    }
+}
+
+/// AxROM switches 32KiB PRG banks and picks the single-screen nametable page through writes to $8000-$FFFF.
+pub struct AxromPrgMapper {
+    prg_rom: Vec<u8>,
+    bank_mask: usize,
+    bank: usize,
+    nametables: NametableSwitch,
+}
+
+impl AxromPrgMapper {
+    pub fn new(rom_image: &RomImage, nametables: NametableSwitch) -> Result<Self, RomError> {
+        // Submapper 2 has bus conflicts, which aren't modelled
+        if rom_image.submapper > 1 {
+            return Err(RomError::UnsupportedSubmapper {
+                mapper: rom_image.mapper,
+                submapper: rom_image.submapper,
+            });
+        }
+
+        let banks = rom_image.prg_rom.len() / 32.KiB();
+        if !rom_image.prg_rom.len().is_multiple_of(32.KiB())
+            || !banks.is_power_of_two()
+            || banks > 16
+        {
+            return Err(RomError::InconsistentHeader(
+                "AxROM PRG ROM must be 32KiB to 512KiB in a power of two",
+            ));
+        }
+
+        Ok(Self {
+            prg_rom: rom_image.prg_rom.clone(),
+            bank_mask: banks - 1,
+            bank: 0,
+            nametables,
+        })
+    }
+}
+
+impl BusDevice for AxromPrgMapper {
+    #[inline]
+    fn read(&mut self, address: Address) -> Option<u8> {
+        (address.0 >= 0x8000)
+            .then(|| self.prg_rom[self.bank * 32.KiB() + (address.0 as usize & 0x7FFF)])
+    }
+
+    #[inline]
+    fn write(&mut self, address: Address, data: u8) -> bool {
+        if address.0 >= 0x8000 {
+            self.bank = data as usize & 0x0F & self.bank_mask;
+            self.nametables.set(if data & 0x10 == 0 {
+                NametableArrangement::SingleScreenA
+            } else {
+                NametableArrangement::SingleScreenB
+            });
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// AxROM's PPU side is plain 8KiB CHR RAM, with the nametable page the PRG side last selected.
+pub struct AxromChrMapper {
+    chr: NromChrMapper,
+    nametables: NametableSwitch,
+}
+
+impl AxromChrMapper {
+    pub fn new(rom_image: &RomImage, nametables: NametableSwitch) -> Result<Self, RomError> {
+        Ok(Self {
+            chr: NromChrMapper::new(rom_image)?,
+            nametables,
+        })
+    }
+}
+
+impl BusDevice for AxromChrMapper {
+    #[inline]
+    fn read(&mut self, address: Address) -> Option<u8> {
+        self.chr.read(address)
+    }
+
+    #[inline]
+    fn write(&mut self, address: Address, data: u8) -> bool {
+        self.chr.write(address, data)
+    }
+}
+
+impl NametableMapper for AxromChrMapper {
+    fn nametable_arrangement(&self) -> NametableArrangement {
+        self.nametables.get()
     }
 }
@@ -0,0 +1,253 @@
+use std::f32::consts::PI;
+
+use super::{
+    apu::{Apu, PulseChannel},
+    Region,
+};
+
+/// Taps in each band-limited step, which is also the output latency in samples.
+const STEP_WIDTH: usize = 16;
+/// Sub-sample positions a step can start at.
+const STEP_PHASES: usize = 64;
+/// Passband of the step kernel as a fraction of the output Nyquist rate.
+const STEP_CUTOFF: f32 = 0.9;
+
+/// Stereo position of each channel, from -1.0 for hard left through 0.0 for centre to 1.0 for hard right.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Panning {
+    pub pulse1: f32,
+    pub pulse2: f32,
+    pub triangle: f32,
+    pub noise: f32,
+    pub dmc: f32,
+}
+
+impl Panning {
+    /// Gains for one side, keeping centred channels at full volume in both.
+    fn gains(&self, side: f32) -> [f32; 5] {
+        [
+            self.pulse1,
+            self.pulse2,
+            self.triangle,
+            self.noise,
+            self.dmc,
+        ]
+        .map(|pan| (1.0 + pan.clamp(-1.0, 1.0) * side).min(1.0))
+    }
+}
+
+/// First order filter run at the output rate.
+struct OnePole {
+    high_pass: bool,
+    alpha: f32,
+    input: f32,
+    output: f32,
+}
+
+impl OnePole {
+    fn new(high_pass: bool, cutoff: f32, sample_rate: u32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate as f32;
+        Self {
+            high_pass,
+            alpha: if high_pass {
+                rc / (rc + dt)
+            } else {
+                dt / (rc + dt)
+            },
+            input: 0.0,
+            output: 0.0,
+        }
+    }
+
+    fn filter(&mut self, input: f32) -> f32 {
+        self.output = if self.high_pass {
+            self.alpha * (self.output + input - self.input)
+        } else {
+            self.output + self.alpha * (input - self.output)
+        };
+        self.input = input;
+        self.output
+    }
+}
+
+/// One output channel: the band-limited deltas waiting to be integrated, then the console's filter chain.
+struct Output {
+    gains: [f32; 5],
+    level: f32,
+    deltas: Vec<f32>,
+    sum: f32,
+    filters: [OnePole; 3],
+}
+
+impl Output {
+    fn new(gains: [f32; 5], sample_rate: u32) -> Self {
+        Self {
+            gains,
+            level: 0.0,
+            deltas: vec![0.0; STEP_WIDTH],
+            sum: 0.0,
+            filters: [
+                OnePole::new(true, 90.0, sample_rate),
+                OnePole::new(true, 440.0, sample_rate),
+                OnePole::new(false, 14_000.0, sample_rate),
+            ],
+        }
+    }
+}
+
+/// Mixes the APU channels through the console's non-linear DAC and resamples them to a host rate with
+/// band-limited steps, so square edges don't alias.
+pub struct Mixer {
+    sample_rate: u32,
+    /// Output samples per CPU cycle.
+    step: f64,
+    time: f64,
+    pulse_table: [f32; 31],
+    tnd_table: [f32; 203],
+    kernel: Box<[[f32; STEP_WIDTH]; STEP_PHASES]>,
+    outputs: Vec<Output>,
+}
+
+impl Mixer {
+    pub const DEFAULT_RATE: u32 = 48_000;
+
+    /// Mono output.
+    pub fn new(region: Region, sample_rate: u32) -> Self {
+        Self::with_outputs(
+            region,
+            sample_rate,
+            vec![Output::new([1.0; 5], sample_rate)],
+        )
+    }
+
+    /// Interleaved left and right output.
+    pub fn with_panning(region: Region, sample_rate: u32, panning: Panning) -> Self {
+        let outputs = [-1.0, 1.0]
+            .map(|side| Output::new(panning.gains(side), sample_rate))
+            .into();
+        Self::with_outputs(region, sample_rate, outputs)
+    }
+
+    fn with_outputs(region: Region, sample_rate: u32, outputs: Vec<Output>) -> Self {
+        assert!(sample_rate > 0, "Audio output needs a sample rate");
+        let cpu_rate = region.master_clock_rate() as f64 / region.cpu_divisor() as f64;
+
+        // A Blackman windowed sinc for each sub-sample phase, each normalized so a step rises by exactly its delta
+        let mut kernel = Box::new([[0.0; STEP_WIDTH]; STEP_PHASES]);
+        for (phase, taps) in kernel.iter_mut().enumerate() {
+            let centre = (STEP_WIDTH / 2) as f32 - 1.0 + phase as f32 / STEP_PHASES as f32;
+            for (tap, weight) in taps.iter_mut().enumerate() {
+                let x = tap as f32 - centre;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (PI * STEP_CUTOFF * x).sin() / (PI * STEP_CUTOFF * x)
+                };
+                let window = (x / (STEP_WIDTH / 2) as f32).clamp(-1.0, 1.0) * PI;
+                *weight = sinc * (0.42 + 0.5 * window.cos() + 0.08 * (2.0 * window).cos());
+            }
+            let sum: f32 = taps.iter().sum();
+            taps.iter_mut().for_each(|weight| *weight /= sum);
+        }
+
+        Self {
+            sample_rate,
+            step: sample_rate as f64 / cpu_rate,
+            time: 0.0,
+            pulse_table: core::array::from_fn(|n| match n {
+                0 => 0.0,
+                n => 95.52 / (8128.0 / n as f32 + 100.0),
+            }),
+            tnd_table: core::array::from_fn(|n| match n {
+                0 => 0.0,
+                n => 163.67 / (24329.0 / n as f32 + 100.0),
+            }),
+            kernel,
+            outputs,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// 1 for mono, 2 for interleaved stereo.
+    pub fn channels(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// Reads a mixing table between entries, for channels turned down by panning.
+    fn lookup(table: &[f32], index: f32) -> f32 {
+        let low = (index as usize).min(table.len() - 2);
+        let fraction = index - low as f32;
+        table[low] + (table[low + 1] - table[low]) * fraction
+    }
+
+    /// Samples the APU's channel levels, once per CPU cycle.
+    pub fn clock(&mut self, apu: &Apu) {
+        let levels = [
+            apu.pulse(PulseChannel::One).output(),
+            apu.pulse(PulseChannel::Two).output(),
+            apu.triangle().output(),
+            apu.noise().output(),
+            apu.dmc().output(),
+        ]
+        .map(f32::from);
+
+        let index = self.time as usize;
+        let phase = ((self.time - index as f64) * STEP_PHASES as f64) as usize;
+        for output in &mut self.outputs {
+            let [pulse1, pulse2, triangle, noise, dmc] =
+                core::array::from_fn(|channel| levels[channel] * output.gains[channel]);
+            let level = Self::lookup(&self.pulse_table, pulse1 + pulse2)
+                + Self::lookup(&self.tnd_table, 3.0 * triangle + 2.0 * noise + dmc);
+
+            let delta = level - output.level;
+            if delta != 0.0 {
+                output.level = level;
+                if output.deltas.len() < index + STEP_WIDTH {
+                    output.deltas.resize(index + STEP_WIDTH, 0.0);
+                }
+                for (sample, weight) in output.deltas[index..].iter_mut().zip(&self.kernel[phase]) {
+                    *sample += delta * weight;
+                }
+            }
+        }
+        self.time += self.step;
+    }
+
+    /// Drains the samples finished since the last call, interleaved when panned. Call once per video frame.
+    pub fn end_frame_f32(&mut self) -> Vec<f32> {
+        let count = self.time as usize;
+        self.time -= count as f64;
+
+        let channels = self.outputs.len();
+        let mut samples = vec![0.0; count * channels];
+        for (channel, output) in self.outputs.iter_mut().enumerate() {
+            output
+                .deltas
+                .resize(output.deltas.len().max(count + STEP_WIDTH), 0.0);
+            for (sample, delta) in samples
+                .iter_mut()
+                .skip(channel)
+                .step_by(channels)
+                .zip(output.deltas.drain(..count))
+            {
+                output.sum += delta;
+                *sample = output
+                    .filters
+                    .iter_mut()
+                    .fold(output.sum, |sample, filter| filter.filter(sample));
+            }
+        }
+        samples
+    }
+
+    pub fn end_frame_i16(&mut self) -> Vec<i16> {
+        self.end_frame_f32()
+            .into_iter()
+            .map(|sample| (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+            .collect()
+    }
+}
@@ -0,0 +1,71 @@
+use super::{
+    palette::{composite_level, NtscSettings, Rgb},
+    ppu::{FRAME_HEIGHT, FRAME_WIDTH},
+};
+
+/// A dot is 8 half-master-clock samples of a 12-sample subcarrier cycle.
+const SAMPLES_PER_DOT: usize = 8;
+const LINE_SAMPLES: usize = FRAME_WIDTH * SAMPLES_PER_DOT;
+/// A scanline is 341 dots, so each one starts 4 phases on from the last.
+const LINE_PHASE_STEP: usize = 341 * SAMPLES_PER_DOT % 12;
+const LUMA_WINDOW: usize = 12;
+const CHROMA_WINDOW: usize = 24;
+
+/// Simulates the composite video signal, decoding the frame from the waveform the PPU puts out rather than from a
+/// palette, so colours bleed between neighbouring pixels and the artefacts crawl from frame to frame.
+pub struct NtscFilter {
+    width: usize,
+    settings: NtscSettings,
+}
+
+impl NtscFilter {
+    pub fn new(width: usize) -> Self {
+        Self::with_settings(width, NtscSettings::default())
+    }
+
+    pub fn with_settings(width: usize, settings: NtscSettings) -> Self {
+        assert!(width > 0, "NTSC output needs at least one column");
+        Self { width, settings }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Decodes a PPU framebuffer into `width` by 240 RGB pixels. The subcarrier phase repeats every three frames.
+    pub fn filter(&self, framebuffer: &[u16; FRAME_WIDTH * FRAME_HEIGHT], frame: u64) -> Vec<Rgb> {
+        let carriers: [(f32, f32); 12] = core::array::from_fn(|phase| self.settings.carrier(phase));
+        let frame_phase = (frame % 3) as usize * LINE_PHASE_STEP;
+        let mut signal = vec![0.0f32; LINE_SAMPLES];
+        let mut output = Vec::with_capacity(self.width * FRAME_HEIGHT);
+
+        for (scanline, pixels) in framebuffer.chunks_exact(FRAME_WIDTH).enumerate() {
+            let line_phase = frame_phase + scanline * LINE_PHASE_STEP;
+            for (sample, level) in signal.iter_mut().enumerate() {
+                *level =
+                    composite_level(pixels[sample / SAMPLES_PER_DOT], (line_phase + sample) % 12);
+            }
+
+            // Box filters a subcarrier cycle wide for luma and two wide for chroma, centred on each column and slid
+            // inside the line at either edge so they always span whole cycles
+            let window = |centre: usize, width: usize| {
+                let start = centre.saturating_sub(width / 2).min(LINE_SAMPLES - width);
+                start..start + width
+            };
+            for column in 0..self.width {
+                let centre = (column * 2 + 1) * LINE_SAMPLES / (self.width * 2);
+                let luma = window(centre, LUMA_WINDOW);
+                let y = signal[luma.clone()].iter().sum::<f32>() / luma.len() as f32;
+                let chroma = window(centre, CHROMA_WINDOW);
+                let (i, q) = chroma.clone().fold((0.0, 0.0), |(i, q), sample| {
+                    let (cos, sin) = carriers[(line_phase + sample) % 12];
+                    (i + signal[sample] * cos, q + signal[sample] * sin)
+                });
+                let length = chroma.len() as f32;
+                output.push(self.settings.yiq_to_rgb(y, i / length, q / length));
+            }
+        }
+
+        output
+    }
+}
@@ -0,0 +1,270 @@
+use core::fmt;
+use std::{
+    error,
+    f32::consts::PI,
+    io::{self, Read},
+};
+
+use super::rom::VsPpuType;
+
+pub type Rgb = [u8; 3];
+
+const COLOURS: usize = 64;
+const EMPHASIS_COLOURS: usize = COLOURS * 8;
+
+/// Converts PPU pixels, a 6-bit colour with the red, green and blue emphasis bits in bits 6-8, to RGB.
+#[derive(Clone)]
+pub struct Palette {
+    colours: Box<[Rgb; EMPHASIS_COLOURS]>,
+}
+
+/// Picture controls for the generated NTSC palette.
+#[derive(Debug, Clone, Copy)]
+pub struct NtscSettings {
+    /// Degrees added to the colour burst phase.
+    pub hue: f32,
+    pub saturation: f32,
+    pub contrast: f32,
+    pub brightness: f32,
+    pub gamma: f32,
+}
+
+impl Default for NtscSettings {
+    fn default() -> Self {
+        Self {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+            brightness: 0.0,
+            gamma: 1.8,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PaletteError {
+    /// `.pal` files hold either 64 or 512 colours.
+    BadSize(usize),
+    Io(io::Error),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaletteError::BadSize(size) => {
+                write!(f, "palette is {size} bytes, expected 192 or 1536")
+            }
+            PaletteError::Io(error) => error.fmt(f),
+        }
+    }
+}
+
+impl error::Error for PaletteError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            PaletteError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PaletteError {
+    fn from(error: io::Error) -> Self {
+        PaletteError::Io(error)
+    }
+}
+
+/// The 2C03 and 2C05 palette as 3-bit red, green and blue digits.
+#[rustfmt::skip]
+const RGB_PPU_2C03: [u16; COLOURS] = [
+    0o333, 0o014, 0o006, 0o326, 0o403, 0o503, 0o510, 0o420, 0o320, 0o120, 0o031, 0o040, 0o022, 0o000, 0o000, 0o000,
+    0o555, 0o036, 0o027, 0o407, 0o507, 0o704, 0o700, 0o630, 0o430, 0o140, 0o040, 0o053, 0o044, 0o000, 0o000, 0o000,
+    0o777, 0o357, 0o447, 0o637, 0o707, 0o737, 0o740, 0o750, 0o660, 0o360, 0o070, 0o276, 0o077, 0o000, 0o000, 0o000,
+    0o777, 0o567, 0o657, 0o757, 0o747, 0o755, 0o764, 0o772, 0o773, 0o572, 0o473, 0o276, 0o467, 0o000, 0o000, 0o000,
+];
+
+/// The four 2C04 variants, each a different arrangement of the same colours.
+#[rustfmt::skip]
+const RGB_PPU_2C04: [[u16; COLOURS]; 4] = [
+    [
+        0o755, 0o637, 0o700, 0o447, 0o044, 0o120, 0o222, 0o704, 0o777, 0o333, 0o750, 0o503, 0o403, 0o660, 0o320, 0o777,
+        0o357, 0o653, 0o310, 0o360, 0o467, 0o657, 0o764, 0o027, 0o760, 0o276, 0o000, 0o200, 0o666, 0o444, 0o707, 0o014,
+        0o003, 0o567, 0o757, 0o070, 0o077, 0o022, 0o053, 0o507, 0o000, 0o420, 0o747, 0o510, 0o407, 0o006, 0o740, 0o000,
+        0o000, 0o140, 0o555, 0o031, 0o572, 0o326, 0o770, 0o630, 0o020, 0o036, 0o040, 0o111, 0o773, 0o737, 0o430, 0o473,
+    ],
+    [
+        0o000, 0o750, 0o430, 0o572, 0o473, 0o737, 0o044, 0o567, 0o700, 0o407, 0o773, 0o747, 0o777, 0o637, 0o467, 0o040,
+        0o020, 0o357, 0o510, 0o666, 0o053, 0o360, 0o200, 0o447, 0o222, 0o707, 0o003, 0o276, 0o657, 0o320, 0o000, 0o326,
+        0o403, 0o764, 0o740, 0o757, 0o036, 0o310, 0o555, 0o006, 0o507, 0o760, 0o333, 0o120, 0o027, 0o000, 0o660, 0o777,
+        0o653, 0o111, 0o070, 0o630, 0o022, 0o014, 0o704, 0o140, 0o000, 0o077, 0o420, 0o770, 0o755, 0o503, 0o031, 0o444,
+    ],
+    [
+        0o507, 0o737, 0o473, 0o555, 0o040, 0o777, 0o567, 0o120, 0o014, 0o000, 0o764, 0o320, 0o704, 0o666, 0o653, 0o467,
+        0o447, 0o044, 0o503, 0o027, 0o140, 0o430, 0o630, 0o053, 0o333, 0o326, 0o000, 0o006, 0o700, 0o510, 0o747, 0o755,
+        0o637, 0o020, 0o003, 0o770, 0o111, 0o750, 0o740, 0o777, 0o360, 0o403, 0o357, 0o707, 0o036, 0o444, 0o000, 0o310,
+        0o077, 0o200, 0o572, 0o757, 0o420, 0o070, 0o660, 0o222, 0o031, 0o000, 0o657, 0o773, 0o407, 0o276, 0o760, 0o022,
+    ],
+    [
+        0o430, 0o326, 0o044, 0o660, 0o000, 0o755, 0o014, 0o630, 0o555, 0o310, 0o070, 0o003, 0o764, 0o770, 0o040, 0o572,
+        0o737, 0o200, 0o027, 0o747, 0o000, 0o222, 0o510, 0o740, 0o653, 0o053, 0o447, 0o140, 0o403, 0o000, 0o473, 0o357,
+        0o503, 0o031, 0o420, 0o006, 0o407, 0o507, 0o333, 0o704, 0o022, 0o666, 0o036, 0o020, 0o111, 0o773, 0o444, 0o707,
+        0o757, 0o777, 0o320, 0o700, 0o760, 0o276, 0o777, 0o467, 0o000, 0o750, 0o637, 0o567, 0o360, 0o657, 0o077, 0o120,
+    ],
+];
+
+/// How much an emphasis bit dims the parts of the signal it applies to.
+const EMPHASIS_ATTENUATION: f32 = 0.746;
+
+/// The 2C02's square wave for a pixel at one of the twelve subcarrier phases, with black at 0 and white at 1.
+pub(super) fn composite_level(pixel: u16, phase: usize) -> f32 {
+    // Voltages of the square wave for the four luma levels, relative to sync
+    const LOW: [f32; 4] = [0.350, 0.518, 0.962, 1.550];
+    const HIGH: [f32; 4] = [1.094, 1.506, 1.962, 1.962];
+    const BLACK: f32 = 0.518;
+    const WHITE: f32 = 1.962;
+    let in_phase = |colour: usize, phase: usize| (colour + phase + 8) % 12 < 6;
+
+    let colour = pixel as usize & 0x0F;
+    let level = if colour < 0x0E {
+        (pixel as usize >> 4) & 0b11
+    } else {
+        1
+    };
+    let low = if colour == 0x00 {
+        HIGH[level]
+    } else {
+        LOW[level]
+    };
+    let high = if colour < 0x0D {
+        HIGH[level]
+    } else {
+        LOW[level]
+    };
+
+    let mut signal = if in_phase(colour, phase) { high } else { low };
+    if (pixel & 0x040 != 0 && in_phase(12, phase))
+        || (pixel & 0x080 != 0 && in_phase(4, phase))
+        || (pixel & 0x100 != 0 && in_phase(8, phase))
+    {
+        signal *= EMPHASIS_ATTENUATION;
+    }
+    (signal - BLACK) / (WHITE - BLACK)
+}
+
+impl NtscSettings {
+    /// The I and Q demodulation carriers at a subcarrier phase.
+    pub(super) fn carrier(&self, phase: usize) -> (f32, f32) {
+        let (sin, cos) = (PI * phase as f32 / 6.0 + self.hue.to_radians()).sin_cos();
+        (cos, sin)
+    }
+
+    pub(super) fn yiq_to_rgb(&self, y: f32, i: f32, q: f32) -> Rgb {
+        let y = y * self.contrast + self.brightness;
+        let i = i * self.saturation * self.contrast;
+        let q = q * self.saturation * self.contrast;
+        let to_byte = |value: f32| {
+            let value = if value <= 0.0 {
+                0.0
+            } else {
+                value.powf(2.2 / self.gamma)
+            };
+            (value * 255.0).round().clamp(0.0, 255.0) as u8
+        };
+
+        [
+            to_byte(y + 0.946882 * i + 0.623557 * q),
+            to_byte(y - 0.274788 * i - 0.635691 * q),
+            to_byte(y - 1.108545 * i + 1.709007 * q),
+        ]
+    }
+}
+
+impl Palette {
+    pub fn rgb(&self, pixel: u16) -> Rgb {
+        self.colours[pixel as usize % EMPHASIS_COLOURS]
+    }
+
+    /// Decodes the composite signal the 2C02 generates for every colour and emphasis combination.
+    pub fn ntsc(settings: &NtscSettings) -> Self {
+        let mut colours = Box::new([[0u8; 3]; EMPHASIS_COLOURS]);
+        for (pixel, rgb) in colours.iter_mut().enumerate() {
+            let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+            for phase in 0..12 {
+                let level = composite_level(pixel as u16, phase) / 12.0;
+                let (cos, sin) = settings.carrier(phase);
+                y += level;
+                i += level * cos;
+                q += level * sin;
+            }
+            *rgb = settings.yiq_to_rgb(y, i, q);
+        }
+
+        Self { colours }
+    }
+
+    /// Reads a `.pal` file of 64 colours, or of 512 with every emphasis combination.
+    pub fn load<R: Read>(mut reader: R) -> Result<Self, PaletteError> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PaletteError> {
+        let rgb = |index: usize| -> Rgb { bytes[index * 3..index * 3 + 3].try_into().unwrap() };
+        let mut colours = Box::new([[0u8; 3]; EMPHASIS_COLOURS]);
+        match bytes.len() {
+            192 => {
+                // Emphasising one channel dims the other two
+                for (pixel, colour) in colours.iter_mut().enumerate() {
+                    let emphasis = pixel >> 6;
+                    *colour = rgb(pixel % COLOURS);
+                    for (channel, value) in colour.iter_mut().enumerate() {
+                        if emphasis & !(1 << channel) != 0 {
+                            *value = (*value as f32 * EMPHASIS_ATTENUATION).round() as u8;
+                        }
+                    }
+                }
+            }
+            1536 => {
+                for (pixel, colour) in colours.iter_mut().enumerate() {
+                    *colour = rgb(pixel);
+                }
+            }
+            size => return Err(PaletteError::BadSize(size)),
+        }
+
+        Ok(Self { colours })
+    }
+
+    /// The fixed RGB palettes of the Vs. System and PlayChoice PPUs, where emphasis turns a channel fully on.
+    pub fn rgb_ppu(ppu_type: VsPpuType) -> Self {
+        let table = match ppu_type {
+            VsPpuType::Rp2C04_0001 => &RGB_PPU_2C04[0],
+            VsPpuType::Rp2C04_0002 => &RGB_PPU_2C04[1],
+            VsPpuType::Rp2C04_0003 => &RGB_PPU_2C04[2],
+            VsPpuType::Rp2C04_0004 => &RGB_PPU_2C04[3],
+            _ => &RGB_PPU_2C03,
+        };
+
+        let mut colours = Box::new([[0u8; 3]; EMPHASIS_COLOURS]);
+        for (pixel, colour) in colours.iter_mut().enumerate() {
+            let digits = table[pixel % COLOURS];
+            let emphasis = pixel >> 6;
+            *colour = [2, 1, 0].map(|digit| (digits >> (digit * 3)) as u8 & 0b111);
+            for (channel, value) in colour.iter_mut().enumerate() {
+                if emphasis & (1 << channel) != 0 {
+                    *value = 0b111;
+                }
+                *value = (*value as u16 * 255 / 7) as u8;
+            }
+        }
+
+        Self { colours }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::ntsc(&NtscSettings::default())
+    }
+}
@@ -1,11 +1,11 @@
+use std::mem;
+
 use bitfields::bitfield;
+use bitflags::bitflags;
 use strum::FromRepr;
 
-use crate::{
-    devices::{BusDevice, RamBank},
-    macros::from_bits,
-    Address, AddressMask,
-};
+use super::{rom::NametableLayout, Region};
+use crate::{devices::BusDevice, macros::from_bits, Address, AddressMask};
 
 use crate::ByteUnits as _;
 
@@ -76,64 +76,635 @@ struct MaskFlags {
     blue_emphasize: bool,
 }
 
-#[repr(u8)]
-#[derive(Default, FromRepr, Clone, Copy)]
-enum StatusFlags {
-    #[default]
-    Default = 0,
-    SpriteOverflow = 0b0010_0000,
-    Sprite0Hit = 0b0100_0000,
-    VBlankFlag = 0b1000_0000,
+bitflags! {
+    #[derive(Default, Clone, Copy)]
+    struct StatusFlags : u8 {
+        const SpriteOverflow = 0b0010_0000;
+        const Sprite0Hit = 0b0100_0000;
+        const VBlankFlag = 0b1000_0000;
+    }
 }
 
-pub struct Ppu<Mapper: BusDevice> {
+/// Which 1KiB page of VRAM each of the nametables at $2000, $2400, $2800 and $2C00 uses. Named after the
+/// arrangement of the distinct nametables, so `Horizontal` is what is usually called vertical mirroring.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NametableArrangement {
+    Horizontal,
+    Vertical,
+    SingleScreenA,
+    SingleScreenB,
+    /// Pages 2 and 3 are the cartridge's extra 2KiB of VRAM, see [`NametableMapper::cartridge_vram`].
+    FourScreen,
+    Mapped([u8; 4]),
+}
+
+impl NametableArrangement {
+    pub fn pages(self) -> [u8; 4] {
+        match self {
+            NametableArrangement::Horizontal => [0, 1, 0, 1],
+            NametableArrangement::Vertical => [0, 0, 1, 1],
+            NametableArrangement::SingleScreenA => [0; 4],
+            NametableArrangement::SingleScreenB => [1; 4],
+            NametableArrangement::FourScreen => [0, 1, 2, 3],
+            NametableArrangement::Mapped(pages) => pages,
+        }
+    }
+}
+
+impl From<NametableLayout> for NametableArrangement {
+    fn from(layout: NametableLayout) -> Self {
+        match layout {
+            NametableLayout::Horizontal => NametableArrangement::Horizontal,
+            NametableLayout::Vertical => NametableArrangement::Vertical,
+        }
+    }
+}
+
+/// The PPU half of a cartridge, which also wires up the nametables and may rewire them at any time.
+pub trait NametableMapper: BusDevice {
+    fn nametable_arrangement(&self) -> NametableArrangement;
+
+    /// VRAM on the cartridge for nametable pages 2 and 3. Without it those pages fall back on the console's two.
+    fn cartridge_vram(&mut self) -> Option<&mut [u8; 2 * usize::K]> {
+        None
+    }
+}
+
+impl<Mapper: NametableMapper + ?Sized> NametableMapper for Box<Mapper> {
+    fn nametable_arrangement(&self) -> NametableArrangement {
+        (**self).nametable_arrangement()
+    }
+
+    fn cartridge_vram(&mut self) -> Option<&mut [u8; 2 * usize::K]> {
+        (**self).cartridge_vram()
+    }
+}
+
+pub struct Ppu<Mapper: NametableMapper> {
     control_flags: ControlFlags,
     mask_flags: MaskFlags,
     status: StatusFlags,
+    /// The I/O bus between the CPU and the PPU registers, whose bits fade to 0 unless driven.
     data_latch: u8,
+    latch_refreshed: [u64; 8],
+    latch_decay: u64,
+    dots: u64,
+    read_buffer: u8,
     oam_address: u8,
     oam: [u8; 256],
-    scroll_x: u16,
-    scroll_y: u16,
-    bus_address: Address,
+    /// Loopy's v: the current VRAM address, laid out as fine Y, nametable, coarse Y and coarse X.
+    vram_address: u16,
+    /// Loopy's t: the address copied into v at the start of each scanline and frame.
+    temp_address: u16,
+    fine_x: u8,
     bus: PpuBus<Mapper>,
     write_swap: bool,
+    dot: u16,
+    scanline: u16,
+    region: Region,
+    odd_frame: bool,
+    frame: u64,
+    suppress_vblank: bool,
+    nmi_delay: u8,
+    background: BackgroundFetch,
+    evaluation: SpriteEvaluation,
+    sprites: [SpriteUnit; 8],
+    sprite_count: usize,
+    sprite_zero_loaded: bool,
+    /// The frame being drawn, swapped with `framebuffer` as it completes.
+    back_buffer: Box<[u16; FRAME_WIDTH * FRAME_HEIGHT]>,
+    framebuffer: Box<[u16; FRAME_WIDTH * FRAME_HEIGHT]>,
+}
+
+/// Secondary OAM and the progress of the sprite evaluation for the next scanline.
+#[derive(Default)]
+struct SpriteEvaluation {
+    secondary_oam: [u8; 32],
+    latch: u8,
+    n: u8,
+    m: u8,
+    written: usize,
+    done: bool,
+    sprite_zero: bool,
+}
+
+/// One of the eight sprites fetched for the current scanline.
+#[derive(Default, Clone, Copy)]
+struct SpriteUnit {
+    pattern_low: u8,
+    pattern_high: u8,
+    attributes: u8,
+    x: u8,
+}
+
+impl SpriteUnit {
+    fn pixel(&self, x: usize) -> Option<u8> {
+        let column = x
+            .checked_sub(self.x as usize)
+            .filter(|column| *column < 8)?;
+        let bit = 7 - column;
+        let colour = (self.pattern_high >> bit & 1) << 1 | (self.pattern_low >> bit & 1);
+        (colour != 0).then_some(colour)
+    }
+
+    fn behind_background(&self) -> bool {
+        self.attributes & 0b0010_0000 != 0
+    }
 }
 
-impl<Mapper: BusDevice> Ppu<Mapper> {
+const PALETTE_BASE: u16 = 0x3F00;
+
+pub const FRAME_WIDTH: usize = 256;
+pub const FRAME_HEIGHT: usize = 240;
+
+/// Tile fetch latches and the 16-bit shift registers that feed the background pixel.
+#[derive(Default)]
+struct BackgroundFetch {
+    tile: u8,
+    attribute: u8,
+    pattern_low: u8,
+    pattern_high: u8,
+    pattern_low_shift: u16,
+    pattern_high_shift: u16,
+    attribute_low_shift: u16,
+    attribute_high_shift: u16,
+}
+
+impl BackgroundFetch {
+    fn reload(&mut self) {
+        self.pattern_low_shift = (self.pattern_low_shift & 0xFF00) | self.pattern_low as u16;
+        self.pattern_high_shift = (self.pattern_high_shift & 0xFF00) | self.pattern_high as u16;
+        self.attribute_low_shift =
+            (self.attribute_low_shift & 0xFF00) | if self.attribute & 1 != 0 { 0xFF } else { 0 };
+        self.attribute_high_shift =
+            (self.attribute_high_shift & 0xFF00) | if self.attribute & 2 != 0 { 0xFF } else { 0 };
+    }
+
+    fn shift(&mut self) {
+        self.pattern_low_shift <<= 1;
+        self.pattern_high_shift <<= 1;
+        self.attribute_low_shift <<= 1;
+        self.attribute_high_shift <<= 1;
+    }
+
+    /// The palette-relative pixel under fine X: palette in bits 2-3, colour in bits 0-1.
+    fn pixel(&self, fine_x: u8) -> u8 {
+        let bit = |shift: u16| ((shift << fine_x) >> 15) as u8;
+        bit(self.attribute_high_shift) << 3
+            | bit(self.attribute_low_shift) << 2
+            | bit(self.pattern_high_shift) << 1
+            | bit(self.pattern_low_shift)
+    }
+}
+
+impl<Mapper: NametableMapper> Ppu<Mapper> {
     const ADDRESS_MASK: AddressMask = AddressMask::from_block(Address(0x2000), 3, 10);
 
-    pub fn new(mapper: Mapper) -> Self {
+    pub fn new(mapper: Mapper, region: Region) -> Self {
         Self {
             control_flags: Default::default(),
             mask_flags: Default::default(),
             status: Default::default(),
             data_latch: Default::default(),
+            latch_refreshed: [0; 8],
+            latch_decay: region.master_clock_rate() * 6 / 10 / region.ppu_divisor(),
+            dots: 0,
+            read_buffer: 0,
             oam_address: Default::default(),
             oam: [0u8; 256],
-            scroll_x: Default::default(),
-            scroll_y: Default::default(),
-            bus_address: Default::default(),
+            vram_address: 0,
+            temp_address: 0,
+            fine_x: 0,
             bus: PpuBus::new(mapper),
             write_swap: Default::default(),
+            dot: 0,
+            scanline: 0,
+            region,
+            odd_frame: false,
+            frame: 0,
+            suppress_vblank: false,
+            nmi_delay: 0,
+            background: BackgroundFetch::default(),
+            evaluation: SpriteEvaluation::default(),
+            sprites: [SpriteUnit::default(); 8],
+            sprite_count: 0,
+            sprite_zero_loaded: false,
+            back_buffer: Box::new([0; FRAME_WIDTH * FRAME_HEIGHT]),
+            framebuffer: Box::new([0; FRAME_WIDTH * FRAME_HEIGHT]),
+        }
+    }
+
+    const DOTS_PER_SCANLINE: u16 = 341;
+
+    /// Advances the PPU by one dot.
+    pub fn tick(&mut self) {
+        self.dots += 1;
+        self.dot += 1;
+        // The NTSC pre-render line is one dot short on odd frames while rendering.
+        let short_line = self.region == Region::Ntsc
+            && self.odd_frame
+            && self.rendering_enabled()
+            && self.scanline == self.pre_render_scanline();
+        if self.dot == Self::DOTS_PER_SCANLINE || (short_line && self.dot == 340) {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline == FRAME_HEIGHT as u16 {
+                self.frame += 1;
+                mem::swap(&mut self.framebuffer, &mut self.back_buffer);
+            } else if self.scanline == self.region.scanlines() {
+                self.scanline = 0;
+                self.odd_frame = !self.odd_frame;
+            }
+        }
+
+        let visible = self.scanline < FRAME_HEIGHT as u16;
+        if self.rendering_enabled() && (visible || self.scanline == self.pre_render_scanline()) {
+            self.fetch_background();
+            if visible {
+                self.evaluate_sprites();
+            }
+            self.fetch_sprites();
+        }
+
+        if visible && (1..=FRAME_WIDTH as u16).contains(&self.dot) {
+            self.output_pixel();
+        }
+
+        if self.dot == 1 {
+            if self.scanline == self.region.vblank_scanline() {
+                if !self.suppress_vblank {
+                    self.status.insert(StatusFlags::VBlankFlag);
+                }
+                self.suppress_vblank = false;
+            } else if self.scanline == self.pre_render_scanline() {
+                self.status = StatusFlags::empty();
+            }
+        }
+
+        self.nmi_delay = self.nmi_delay << 1 | self.nmi_line() as u8;
+    }
+
+    fn nmi_line(&self) -> bool {
+        self.status.contains(StatusFlags::VBlankFlag) && self.control_flags.vblank_nmi_enable()
+    }
+
+    /// Every pixel of the last completed frame, held while the next is drawn, row by row, as the 6-bit colour with
+    /// the emphasis bits of [`Ppu::emphasis`] above it. [`super::palette::Palette`] turns these into RGB.
+    pub fn framebuffer(&self) -> &[u16; FRAME_WIDTH * FRAME_HEIGHT] {
+        &self.framebuffer
+    }
+
+    /// The number of frames completed, incremented once the last visible scanline is drawn.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    fn pre_render_scanline(&self) -> u16 {
+        self.region.scanlines() - 1
+    }
+
+    fn rendering_enabled(&self) -> bool {
+        self.mask_flags.render_background() || self.mask_flags.render_sprite()
+    }
+
+    fn sprite_height(&self) -> u16 {
+        match self.control_flags.sprite_size() {
+            SpriteSize::Size8x8 => 8,
+            SpriteSize::Size8x16 => 16,
+        }
+    }
+
+    fn sprite_in_range(&self, y: u8) -> bool {
+        self.scanline
+            .checked_sub(y as u16)
+            .is_some_and(|row| row < self.sprite_height())
+    }
+
+    /// Fills secondary OAM for the next scanline: reads on odd dots and writes on even dots.
+    fn evaluate_sprites(&mut self) {
+        let dot = self.dot;
+        match dot {
+            1..=64 if dot.is_multiple_of(2) => {
+                self.evaluation.secondary_oam[dot as usize / 2 - 1] = 0xFF
+            }
+            65..=256 if !dot.is_multiple_of(2) => {
+                if dot == 65 {
+                    self.evaluation = SpriteEvaluation {
+                        secondary_oam: self.evaluation.secondary_oam,
+                        ..Default::default()
+                    };
+                }
+                let evaluation = &mut self.evaluation;
+                evaluation.latch =
+                    self.oam[(evaluation.n as usize % 64) * 4 + evaluation.m as usize];
+            }
+            65..=256 => {
+                let latch = self.evaluation.latch;
+                let in_range = self.sprite_in_range(latch);
+                let evaluation = &mut self.evaluation;
+                if evaluation.done {
+                    return;
+                }
+
+                if evaluation.written < evaluation.secondary_oam.len() {
+                    evaluation.secondary_oam[evaluation.written] = latch;
+                    if evaluation.m == 0 && !in_range {
+                        evaluation.n += 1;
+                    } else {
+                        evaluation.sprite_zero |= evaluation.n == 0;
+                        evaluation.written += 1;
+                        evaluation.m = (evaluation.m + 1) & 0b11;
+                        if evaluation.m == 0 {
+                            evaluation.n += 1;
+                        }
+                    }
+                } else if in_range {
+                    self.status.insert(StatusFlags::SpriteOverflow);
+                    evaluation.done = true;
+                } else {
+                    // The hardware increments m along with n here, so it checks a diagonal of OAM bytes as Y.
+                    evaluation.n += 1;
+                    evaluation.m = (evaluation.m + 1) & 0b11;
+                }
+                evaluation.done |= evaluation.n == 64;
+            }
+            _ => {}
+        }
+    }
+
+    /// Loads the sprites found by evaluation into the sprite units during dots 257-320.
+    fn fetch_sprites(&mut self) {
+        if !(257..=320).contains(&self.dot) {
+            return;
+        }
+        self.oam_address = 0;
+
+        let slot = (self.dot as usize - 257) / 8;
+        if (self.dot - 257) % 8 != 7 {
+            return;
+        }
+        if self.scanline == self.pre_render_scanline() {
+            self.sprite_count = 0;
+            self.sprite_zero_loaded = false;
+            return;
+        }
+
+        let [y, tile, attributes, x] = self.evaluation.secondary_oam[slot * 4..slot * 4 + 4]
+            .try_into()
+            .unwrap();
+        let height = self.sprite_height();
+        let mut row = self.scanline.wrapping_sub(y as u16) & (height - 1);
+        if attributes & 0b1000_0000 != 0 {
+            row = height - 1 - row;
+        }
+        let (bank, tile) = match self.control_flags.sprite_size() {
+            SpriteSize::Size8x8 => (self.control_flags.sprite_pattern_bank() as u16, tile as u16),
+            SpriteSize::Size8x16 => ((tile & 1) as u16, (tile & 0xFE) as u16 | row >> 3),
+        };
+        let address = bank << 12 | tile << 4 | row & 0b111;
+        let (mut pattern_low, mut pattern_high) =
+            (self.read_bus(address), self.read_bus(address + 8));
+        if attributes & 0b0100_0000 != 0 {
+            pattern_low = pattern_low.reverse_bits();
+            pattern_high = pattern_high.reverse_bits();
+        }
+
+        let found = slot < self.evaluation.written / 4;
+        self.sprites[slot] = if found {
+            SpriteUnit {
+                pattern_low,
+                pattern_high,
+                attributes,
+                x,
+            }
+        } else {
+            SpriteUnit::default()
+        };
+        if slot == 7 {
+            self.sprite_count = self.evaluation.written / 4;
+            self.sprite_zero_loaded = self.evaluation.sprite_zero;
         }
     }
 
+    fn fetch_background(&mut self) {
+        let dot = self.dot;
+        let fetching = (1..=256).contains(&dot) || (321..=336).contains(&dot);
+        if (2..=257).contains(&dot) || (322..=337).contains(&dot) {
+            self.background.shift();
+        }
+
+        if fetching {
+            match (dot - 1) % 8 {
+                0 => {
+                    self.background.reload();
+                    self.background.tile = self.read_bus(0x2000 | (self.vram_address & 0x0FFF));
+                }
+                2 => {
+                    let v = self.vram_address;
+                    let attribute = self
+                        .read_bus(0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07));
+                    let shift = ((v >> 4) & 0b100) | (v & 0b10);
+                    self.background.attribute = (attribute >> shift) & 0b11;
+                }
+                4 => self.background.pattern_low = self.read_bus(self.pattern_address()),
+                6 => self.background.pattern_high = self.read_bus(self.pattern_address() + 8),
+                7 => self.increment_coarse_x(),
+                _ => {}
+            }
+        }
+
+        match dot {
+            256 => self.increment_y(),
+            257 => {
+                self.background.reload();
+                self.vram_address = (self.vram_address & !0x041F) | (self.temp_address & 0x041F);
+            }
+            280..=304 if self.scanline == self.pre_render_scanline() => {
+                self.vram_address = (self.vram_address & 0x041F) | (self.temp_address & 0x7BE0);
+            }
+            337 | 339 => {
+                self.read_bus(0x2000 | (self.vram_address & 0x0FFF));
+            }
+            _ => {}
+        }
+    }
+
+    fn pattern_address(&self) -> u16 {
+        (self.control_flags.background_pattern_bank() as u16) << 12
+            | (self.background.tile as u16) << 4
+            | self.vram_address >> 12
+    }
+
+    fn increment_coarse_x(&mut self) {
+        if self.vram_address & 0x1F == 0x1F {
+            self.vram_address = (self.vram_address & !0x1F) ^ 0x0400;
+        } else {
+            self.vram_address += 1;
+        }
+    }
+
+    fn increment_y(&mut self) {
+        if self.vram_address & 0x7000 != 0x7000 {
+            self.vram_address += 0x1000;
+        } else {
+            self.vram_address &= !0x7000;
+            let coarse_y = match (self.vram_address >> 5) & 0x1F {
+                29 => {
+                    self.vram_address ^= 0x0800;
+                    0
+                }
+                31 => 0,
+                coarse_y => coarse_y + 1,
+            };
+            self.vram_address = (self.vram_address & !0x03E0) | coarse_y << 5;
+        }
+    }
+
+    fn output_pixel(&mut self) {
+        let x = self.dot as usize - 1;
+        let background = if self.mask_flags.render_background()
+            && (x >= 8 || self.mask_flags.background_overscan())
+        {
+            self.background.pixel(self.fine_x)
+        } else {
+            0
+        };
+        let background_opaque = background & 0b11 != 0;
+
+        let sprite =
+            if self.mask_flags.render_sprite() && (x >= 8 || self.mask_flags.sprite_overscan()) {
+                self.sprites[..self.sprite_count]
+                    .iter()
+                    .enumerate()
+                    .find_map(|(slot, sprite)| sprite.pixel(x).map(|colour| (slot, sprite, colour)))
+            } else {
+                None
+            };
+
+        // Colour 0 of every palette shows the backdrop at $3F00.
+        let palette_index = match sprite {
+            Some((slot, sprite, colour)) => {
+                if slot == 0 && self.sprite_zero_loaded && background_opaque && x != 255 {
+                    self.status.insert(StatusFlags::Sprite0Hit);
+                }
+                if background_opaque && sprite.behind_background() {
+                    background
+                } else {
+                    0x10 | (sprite.attributes & 0b11) << 2 | colour
+                }
+            }
+            None if background_opaque => background,
+            None => 0,
+        };
+        let colour = self.read_bus(PALETTE_BASE | palette_index as u16) & self.greyscale_mask();
+        self.back_buffer[self.scanline as usize * FRAME_WIDTH + x] =
+            colour as u16 | (self.emphasis() as u16) << 6;
+    }
+
+    /// Greyscale keeps only the luma bits of palette entries.
+    fn greyscale_mask(&self) -> u8 {
+        if self.mask_flags.greyscale_enable() {
+            0x30
+        } else {
+            0x3F
+        }
+    }
+
+    fn read_bus(&mut self, address: u16) -> u8 {
+        self.bus.read(Address(address)).unwrap_or_default()
+    }
+
+    /// The emphasis bits of PPUMASK as red, green and blue in bits 0-2. The 2C07 swaps red and green.
+    pub fn emphasis(&self) -> u8 {
+        let (red, green) = match self.region {
+            Region::Ntsc => (
+                self.mask_flags.red_emphasize(),
+                self.mask_flags.green_emphasize(),
+            ),
+            Region::Pal | Region::Dendy => (
+                self.mask_flags.green_emphasize(),
+                self.mask_flags.red_emphasize(),
+            ),
+        };
+        red as u8 | (green as u8) << 1 | (self.mask_flags.blue_emphasize() as u8) << 2
+    }
+
+    /// The /NMI output, asserted while in vertical blank with NMI enabled in PPUCTRL.
+    /// Lags the internal line by two dots, so clearing the flag or the enable within a dot of the flag being set
+    /// also stops the NMI.
+    pub fn nmi(&self) -> bool {
+        self.nmi_delay & 0b100 != 0
+    }
+
+    pub fn power_on(&mut self) {
+        self.reset();
+        self.status = StatusFlags::empty();
+        self.nmi_delay = 0;
+        self.oam_address = 0;
+        self.vram_address = 0;
+    }
+
+    /// The reset line clears PPUCTRL, PPUMASK, the scroll, the write toggle and the PPUDATA read buffer, but not
+    /// VRAM, OAM or PPUADDR.
+    pub fn reset(&mut self) {
+        self.control_flags = ControlFlags::default();
+        self.mask_flags = MaskFlags::default();
+        self.temp_address = 0;
+        self.fine_x = 0;
+        self.write_swap = false;
+        self.data_latch = 0;
+        self.read_buffer = 0;
+    }
+
     fn ctrl(&mut self, data: u8) {
         self.control_flags = ControlFlags::from_bits(data);
+        if !self.control_flags.vblank_nmi_enable() {
+            self.nmi_delay = 0;
+        }
+        self.temp_address = (self.temp_address & !0x0C00) | (data as u16 & 0b11) << 10;
     }
 
     fn mask(&mut self, data: u8) {
         self.mask_flags = MaskFlags::from_bits(data);
     }
 
+    /// The I/O latch, with any bit that has gone about 600ms without being driven decayed to 0.
+    fn io_latch(&mut self) -> u8 {
+        for (bit, refreshed) in self.latch_refreshed.iter().enumerate() {
+            if self.dots - refreshed >= self.latch_decay {
+                self.data_latch &= !(1 << bit);
+            }
+        }
+        self.data_latch
+    }
+
+    /// Drives the bits of the I/O latch selected by `mask`.
+    fn refresh_latch(&mut self, data: u8, mask: u8) {
+        self.data_latch = (self.data_latch & !mask) | (data & mask);
+        for (bit, refreshed) in self.latch_refreshed.iter_mut().enumerate() {
+            if mask & (1 << bit) != 0 {
+                *refreshed = self.dots;
+            }
+        }
+    }
+
     fn status(&mut self) -> u8 {
+        // A read one dot before vertical blank starts reads it clear and keeps it from being set this frame
+        if self.scanline == self.region.vblank_scanline() && self.dot == 0 {
+            self.suppress_vblank = true;
+        }
         self.write_swap = false;
-        (self.data_latch & 0b0001_1111) | (self.status as u8)
+        let status = (self.io_latch() & 0b0001_1111) | self.status.bits();
+        self.status.remove(StatusFlags::VBlankFlag);
+        self.nmi_delay = 0;
+        self.refresh_latch(status, 0b1110_0000);
+        status
     }
 
-    fn read_oam(&self) -> u8 {
-        self.oam[self.oam_address as usize]
+    fn read_oam(&mut self) -> u8 {
+        let data = self.oam[self.oam_address as usize];
+        self.refresh_latch(data, 0xFF);
+        data
     }
 
     fn write_oam(&mut self, data: u8) {
@@ -143,56 +714,100 @@ impl<Mapper: BusDevice> Ppu<Mapper> {
         // it's plausible that it could bump the low bits instead depending on the current status of sprite
         // evaluation). This extends to DMA transfers via OAMDMA, since that uses writes to $2004. For emulation
         // purposes, it is probably best to completely ignore writes during rendering.
+        // Bits 2-4 of the attribute byte are not implemented and read back as 0.
+        let data = if self.oam_address & 0b11 == 2 {
+            data & 0b1110_0011
+        } else {
+            data
+        };
         self.oam[self.oam_address as usize] = data;
         self.oam_address = self.oam_address.wrapping_add(1);
     }
 
     fn scroll(&mut self, data: u8) {
-        let nametable = self.control_flags.nametable_bank();
         match self.write_swap {
-            false => self.scroll_x = data as u16 | (nametable as u16 & 0b01) << 8,
-            true => self.scroll_y = data as u16 | (nametable as u16 & 0b10) << 7,
+            false => {
+                self.temp_address = (self.temp_address & !0x001F) | data as u16 >> 3;
+                self.fine_x = data & 0b111;
+            }
+            true => {
+                self.temp_address = (self.temp_address & !0x73E0)
+                    | (data as u16 & 0b111) << 12
+                    | (data as u16 >> 3) << 5;
+            }
         }
         self.write_swap = !self.write_swap;
     }
 
     fn addr(&mut self, data: u8) {
         match self.write_swap {
-            false => self.bus_address.set_high(data),
-            true => self.bus_address.set_low(data),
+            false => {
+                self.temp_address = (self.temp_address & 0x00FF) | (data as u16 & 0x3F) << 8;
+            }
+            true => {
+                self.temp_address = (self.temp_address & 0xFF00) | data as u16;
+                self.vram_address = self.temp_address;
+            }
         }
         self.write_swap = !self.write_swap;
     }
 
+    /// Reads go through a one-byte buffer, except palette reads which return at once and buffer the nametable
+    /// byte underneath.
     fn read_vram(&mut self) -> u8 {
-        self.bus.read(self.bus_address).unwrap()
+        let address = self.vram_address & 0x3FFF;
+        let data = if address >= PALETTE_BASE {
+            self.read_buffer = self.read_bus(address - 0x1000);
+            let colour = self.read_bus(address) & self.greyscale_mask();
+            self.refresh_latch(colour, 0x3F);
+            colour | (self.io_latch() & 0xC0)
+        } else {
+            let data = self.read_buffer;
+            self.read_buffer = self.read_bus(address);
+            self.refresh_latch(data, 0xFF);
+            data
+        };
+        self.increment_vram_address();
+        data
     }
 
     fn write_vram(&mut self, data: u8) {
-        self.bus.write(self.bus_address, data);
+        self.bus.write(Address(self.vram_address & 0x3FFF), data);
+        self.increment_vram_address();
+    }
+
+    /// While rendering, PPUDATA accesses bump coarse X and Y instead of adding the increment.
+    fn increment_vram_address(&mut self) {
+        let rendering_line =
+            self.scanline < FRAME_HEIGHT as u16 || self.scanline == self.pre_render_scanline();
+        if self.rendering_enabled() && rendering_line {
+            self.increment_coarse_x();
+            self.increment_y();
+        } else {
+            let increment = match self.control_flags.increment_mode() {
+                IncrementMode::Horizontal => 1,
+                IncrementMode::Vertical => 32,
+            };
+            self.vram_address = (self.vram_address + increment) & 0x7FFF;
+        }
     }
 }
 
-impl<Mapper: BusDevice> BusDevice for Ppu<Mapper> {
+impl<Mapper: NametableMapper> BusDevice for Ppu<Mapper> {
     fn read(&mut self, address: Address) -> Option<u8> {
         Self::ADDRESS_MASK
             .remap(address)
             .map(|register| match register.0 {
-                0 => self.data_latch,
-                1 => self.data_latch,
                 2 => self.status(),
-                3 => self.data_latch,
                 4 => self.read_oam(),
-                5 => self.data_latch,
-                6 => self.data_latch,
                 7 => self.read_vram(),
-                _ => unreachable!(),
+                _ => self.io_latch(),
             })
     }
 
     fn write(&mut self, address: Address, data: u8) -> bool {
         if let Some(register) = Self::ADDRESS_MASK.remap(address) {
-            self.data_latch = data;
+            self.refresh_latch(data, 0xFF);
 
             match register {
                 Address(0) => self.ctrl(data),
@@ -213,32 +828,72 @@ impl<Mapper: BusDevice> BusDevice for Ppu<Mapper> {
     }
 }
 
-struct PpuBus<Mapper: BusDevice> {
-    vram_bank: RamBank<{ 2 * usize::K }>,
+struct PpuBus<Mapper: NametableMapper> {
+    /// The console's own 2KiB of VRAM, nametable pages 0 and 1.
+    vram: [u8; 2 * usize::K],
+    palette: [u8; 32],
     mapper: Mapper,
 }
 
-impl<Mapper: BusDevice> PpuBus<Mapper> {
+impl<Mapper: NametableMapper> PpuBus<Mapper> {
     pub fn new(mapper: Mapper) -> Self {
         Self {
-            vram_bank: RamBank::new(AddressMask::from_block(Address(0x2000), 3, 2)),
+            vram: [0; 2 * usize::K],
+            palette: [0; 32],
             mapper,
         }
     }
+
+    /// $2000-$3EFF through the mapper's nametable arrangement, in the console's VRAM or the cartridge's.
+    fn nametable(&mut self, address: Address) -> Option<&mut u8> {
+        let address = address.0 & 0x3FFF;
+        if !(0x2000..PALETTE_BASE).contains(&address) {
+            return None;
+        }
+
+        let page = self.mapper.nametable_arrangement().pages()[(address as usize >> 10) & 0b11];
+        let offset = address as usize & 0x3FF;
+        match (page & 0b10, self.mapper.cartridge_vram()) {
+            (0b10, Some(vram)) => Some(&mut vram[(page as usize & 1) << 10 | offset]),
+            _ => Some(&mut self.vram[(page as usize & 1) << 10 | offset]),
+        }
+    }
+
+    /// $3F10, $3F14, $3F18 and $3F1C mirror the backdrop entries of the background palettes.
+    fn palette_index(address: Address) -> Option<usize> {
+        (address.0 & 0x3FFF >= PALETTE_BASE).then(|| {
+            let index = address.0 as usize & 0x1F;
+            if index & 0x13 == 0x10 {
+                index & 0x0F
+            } else {
+                index
+            }
+        })
+    }
 }
 
-impl<Mapper: BusDevice> BusDevice for PpuBus<Mapper> {
+impl<Mapper: NametableMapper> BusDevice for PpuBus<Mapper> {
     fn read(&mut self, address: Address) -> Option<u8> {
+        if let Some(index) = Self::palette_index(address) {
+            return Some(self.palette[index]);
+        }
+
         self.mapper
             .read(address)
-            .or_else(|| self.vram_bank.read(address))
+            .or_else(|| self.nametable(address).map(|data| *data))
     }
 
     fn write(&mut self, address: Address, data: u8) -> bool {
-        if !self.mapper.write(address, data) {
-            self.vram_bank.write(address, data)
-        } else {
+        if let Some(index) = Self::palette_index(address) {
+            self.palette[index] = data & 0x3F;
+            true
+        } else if self.mapper.write(address, data) {
+            true
+        } else if let Some(vram) = self.nametable(address) {
+            *vram = data;
             true
+        } else {
+            false
         }
     }
 }
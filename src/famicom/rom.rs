@@ -1,12 +1,17 @@
-use std::io;
+use core::fmt;
+use std::{
+    error,
+    io::{self, Read as _},
+};
 
 use bitfields::bitfield;
 use byteorder::{BigEndian, ByteOrder as _, ReadBytesExt};
 use strum_macros::FromRepr;
 
+use super::ppu::NametableMapper;
 use crate::{macros::from_bits, BusDevice, System};
 
-use super::{SystemBus, RP2A03};
+use super::{Region, SystemBus, RP2A03};
 
 #[repr(u8)]
 #[derive(FromRepr, Clone, Copy, Debug)]
@@ -35,8 +40,8 @@ struct Flags6 {
 }
 
 #[repr(u8)]
-#[derive(FromRepr)]
-enum ConsoleType {
+#[derive(FromRepr, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConsoleType {
     Famicom = 0,
     VsSystem = 1,
     Playchoice10 = 2,
@@ -48,7 +53,9 @@ from_bits!(ConsoleType, u8);
 #[derive(FromRepr)]
 enum INesFormat {
     INes = 0,
+    Archaic = 1,
     Nes2_0 = 2,
+    Reserved = 3,
 }
 from_bits!(INesFormat, u8);
 
@@ -64,24 +71,188 @@ struct Flags7 {
     mapper_mid_nibble: u8,
 }
 
+#[bitfield(u8)]
+struct MapperMsb {
+    #[bits(4)]
+    mapper_high_nibble: u8,
+
+    #[bits(4)]
+    submapper: u8,
+}
+
+#[bitfield(u8)]
+struct RomSizeMsb {
+    #[bits(4)]
+    prg_rom: u8,
+
+    #[bits(4)]
+    chr_rom: u8,
+}
+
+#[bitfield(u8)]
+struct RamShifts {
+    #[bits(4)]
+    volatile: u8,
+
+    #[bits(4)]
+    non_volatile: u8,
+}
+
+#[bitfield(u8)]
+struct VsTypes {
+    #[bits(4)]
+    ppu_type: u8,
+
+    #[bits(4)]
+    hardware_type: u8,
+}
+
+#[repr(u8)]
+#[derive(FromRepr, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VsPpuType {
+    Rp2C03B = 0,
+    Rp2C03G = 1,
+    Rp2C04_0001 = 2,
+    Rp2C04_0002 = 3,
+    Rp2C04_0003 = 4,
+    Rp2C04_0004 = 5,
+    Rc2C03B = 6,
+    Rc2C03C = 7,
+    Rc2C05_01 = 8,
+    Rc2C05_02 = 9,
+    Rc2C05_03 = 10,
+    Rc2C05_04 = 11,
+    Rc2C05_05 = 12,
+}
+
+#[repr(u8)]
+#[derive(FromRepr, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VsHardwareType {
+    UniSystem = 0,
+    UniSystemRbiBaseball = 1,
+    UniSystemTkoBoxing = 2,
+    UniSystemSuperXevious = 3,
+    UniSystemIceClimber = 4,
+    DualSystem = 5,
+    DualSystemRaidOnBungelingBay = 6,
+}
+
+/// Byte 13 of an NES 2.0 header with console type 3.
+#[repr(u8)]
+#[derive(FromRepr, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtendedConsoleType {
+    Famicom = 0,
+    VsSystem = 1,
+    Playchoice10 = 2,
+    /// A famiclone whose CPU has working decimal mode.
+    DecimalFamiclone = 3,
+    /// EPSM module or plug-through cartridge.
+    Epsm = 4,
+    Vt01 = 5,
+    Vt02 = 6,
+    Vt03 = 7,
+    Vt09 = 8,
+    Vt32 = 9,
+    Vt369 = 10,
+    Um6578 = 11,
+    FamicomNetworkSystem = 12,
+}
+
+#[derive(Debug)]
+pub enum RomError {
+    /// The image does not start with `NES<EOF>`.
+    BadMagic,
+    TruncatedHeader,
+    TruncatedPrg {
+        expected: usize,
+        actual: usize,
+    },
+    TruncatedChr {
+        expected: usize,
+        actual: usize,
+    },
+    UnsupportedMapper(u16),
+    UnsupportedSubmapper {
+        mapper: u16,
+        submapper: u8,
+    },
+    /// Header sizes or values that are out of range or that the mapper cannot have.
+    InconsistentHeader(&'static str),
+    Io(io::Error),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::BadMagic => write!(f, "not an iNES image"),
+            RomError::TruncatedHeader => write!(f, "truncated header"),
+            RomError::TruncatedPrg { expected, actual } => {
+                write!(f, "truncated PRG ROM, {actual} of {expected} bytes")
+            }
+            RomError::TruncatedChr { expected, actual } => {
+                write!(f, "truncated CHR ROM, {actual} of {expected} bytes")
+            }
+            RomError::UnsupportedMapper(mapper) => write!(f, "unsupported mapper {mapper}"),
+            RomError::UnsupportedSubmapper { mapper, submapper } => {
+                write!(f, "unsupported submapper {mapper}.{submapper}")
+            }
+            RomError::InconsistentHeader(message) => write!(f, "inconsistent header: {message}"),
+            RomError::Io(error) => error.fmt(f),
+        }
+    }
+}
+
+impl error::Error for RomError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            RomError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RomError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::UnexpectedEof => RomError::TruncatedHeader,
+            _ => RomError::Io(error),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct RomImage {
     pub prg_rom: Vec<u8>,
     pub chr_rom: Vec<u8>,
+    /// 512 bytes loaded into PRG RAM at $7000 by mappers that have it.
+    pub trainer: Option<Vec<u8>>,
     pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
     pub mapper: u16,
     pub submapper: u8,
     pub nametable_layout: NametableLayout,
+    /// Mapper-specific; on boards without their own nametable control it asks for four-screen VRAM.
+    pub alternative_nametables: bool,
+    pub region: Region,
+    pub console_type: ConsoleType,
+    pub vs_ppu_type: Option<VsPpuType>,
+    pub vs_hardware_type: Option<VsHardwareType>,
+    pub extended_console_type: Option<ExtendedConsoleType>,
+    pub misc_rom_count: u8,
+    pub default_expansion_device: u8,
 }
 
 impl RomImage {
-    pub fn load<R: io::Read + io::Seek>(mut reader: R) -> Result<Self, io::Error> {
+    pub fn load<R: io::Read + io::Seek>(mut reader: R) -> Result<Self, RomError> {
         let ines_header: u32 = byteorder::BigEndian::read_u32(b"NES\x1a");
-        // let fk = b"1";
-        let header = reader.read_u32::<BigEndian>()?;
+        let header = reader
+            .read_u32::<BigEndian>()
+            .map_err(|_| RomError::BadMagic)?;
 
         if header != ines_header {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown format"));
+            return Err(RomError::BadMagic);
         }
         let prg_rom_size = reader.read_u8()?;
         let chr_rom_size = reader.read_u8()?;
@@ -90,12 +261,12 @@ impl RomImage {
         let flags7 = Flags7::from_bits(reader.read_u8()?);
 
         match flags7.format_version() {
-            INesFormat::INes => {
-                Self::load_ines_image(prg_rom_size, chr_rom_size, flags6, flags7, reader)
-            }
             INesFormat::Nes2_0 => {
                 Self::load_nes2_image(prg_rom_size, chr_rom_size, flags6, flags7, reader)
             }
+            INesFormat::INes | INesFormat::Archaic | INesFormat::Reserved => {
+                Self::load_ines_image(prg_rom_size, chr_rom_size, flags6, flags7, reader)
+            }
         }
     }
 
@@ -105,15 +276,13 @@ impl RomImage {
         flags6: Flags6,
         flags7: Flags7,
         mut reader: R,
-    ) -> Result<Self, io::Error> {
-        if reader.stream_position().unwrap() != 8 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Image deserialization failure",
-            ));
-        }
-
+    ) -> Result<Self, RomError> {
         let prg_ram_size = (reader.read_u8()? as usize) * 0x2000;
+        // Flags 9 bit 0 selects PAL, although few iNES dumps set it
+        let region = match reader.read_u8()? & 1 {
+            0 => Region::Ntsc,
+            _ => Region::Pal,
+        };
         // PRG ROM size is defined as number of 16KB units.
         let prg_rom_size = (prg_rom_size as usize) * 0x4000;
         // CHR ROM size is defined as number of 8KB units.
@@ -121,56 +290,233 @@ impl RomImage {
 
         reader.seek(io::SeekFrom::Start(16))?;
 
-        if flags6.has_trainer_header() {
-            unimplemented!();
-        }
-
-        let mut prg_rom = vec![0; prg_rom_size];
-        reader.read_exact(prg_rom.as_mut_slice())?;
-        let mut chr_rom = vec![0; chr_rom_size];
-        reader.read_exact(chr_rom.as_mut_slice())?;
+        let trainer = read_trainer(&mut reader, flags6.has_trainer_header())?;
+        let (prg_rom, chr_rom) = read_roms(&mut reader, prg_rom_size, chr_rom_size)?;
 
         let mapper: u16 =
             ((flags7.mapper_mid_nibble() as u16) << 4) | (flags6.mapper_low_nibble() as u16);
 
+        // iNES images without CHR ROM have 8KiB of CHR RAM
+        let chr_ram_size = if chr_rom_size == 0 { 0x2000 } else { 0 };
+
         Ok(Self {
             prg_rom,
             chr_rom,
+            trainer,
             prg_ram_size,
+            prg_nvram_size: 0,
+            chr_ram_size,
+            chr_nvram_size: 0,
             mapper,
             submapper: 0,
             nametable_layout: flags6.nametable_layout(),
+            alternative_nametables: flags6.enable_alternative_nametables(),
+            region,
+            console_type: flags7.console_type(),
+            vs_ppu_type: None,
+            vs_hardware_type: None,
+            extended_console_type: None,
+            misc_rom_count: 0,
+            default_expansion_device: 0,
         })
     }
 
     fn load_nes2_image<R: io::Read + io::Seek>(
-        _prg_rom_size: u8,
-        _chr_rom_size: u8,
+        prg_rom_size: u8,
+        chr_rom_size: u8,
         flags6: Flags6,
         flags7: Flags7,
         mut reader: R,
-    ) -> Result<Self, io::Error> {
-        if reader.stream_position().unwrap() != 8 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Image deserialization failure",
-            ));
-        }
-
-        let mapper_msb = reader.read_u8()?;
-        let _mapper: u16 = ((mapper_msb as u16 & 0xf) << 8)
+    ) -> Result<Self, RomError> {
+        let mapper_msb = MapperMsb::from_bits(reader.read_u8()?);
+        let mapper: u16 = ((mapper_msb.mapper_high_nibble() as u16) << 8)
             | ((flags7.mapper_mid_nibble() as u16) << 4)
             | (flags6.mapper_low_nibble() as u16);
-        let _submapper = mapper_msb >> 4;
-        let _rom_size_msb = reader.read_u8()?;
+        let rom_size_msb = RomSizeMsb::from_bits(reader.read_u8()?);
+        let prg_ram_shifts = RamShifts::from_bits(reader.read_u8()?);
+        let chr_ram_shifts = RamShifts::from_bits(reader.read_u8()?);
+        let region = Region::from_timing(reader.read_u8()?);
+        // Byte 13 holds the Vs. System types, or the extended console type in its low nibble
+        let console_types = reader.read_u8()?;
+        let vs_types = VsTypes::from_bits(console_types);
+        let misc_rom_count = reader.read_u8()? & 0b11;
+        let default_expansion_device = reader.read_u8()? & 0b11_1111;
+
+        let (vs_ppu_type, vs_hardware_type) = match flags7.console_type() {
+            ConsoleType::VsSystem => (
+                Some(
+                    VsPpuType::from_repr(vs_types.ppu_type())
+                        .ok_or(RomError::InconsistentHeader("unknown Vs. System PPU type"))?,
+                ),
+                Some(VsHardwareType::from_repr(vs_types.hardware_type()).ok_or(
+                    RomError::InconsistentHeader("unknown Vs. System hardware type"),
+                )?),
+            ),
+            _ => (None, None),
+        };
+        let extended_console_type = match flags7.console_type() {
+            ConsoleType::Extended => {
+                Some(ExtendedConsoleType::from_repr(console_types & 0x0F).ok_or(
+                    RomError::InconsistentHeader("unknown extended console type"),
+                )?)
+            }
+            _ => None,
+        };
+
+        let prg_rom_size = nes2_rom_size(prg_rom_size, rom_size_msb.prg_rom(), 0x4000)?;
+        let chr_rom_size = nes2_rom_size(chr_rom_size, rom_size_msb.chr_rom(), 0x2000)?;
+
+        let trainer = read_trainer(&mut reader, flags6.has_trainer_header())?;
+        let (prg_rom, chr_rom) = read_roms(&mut reader, prg_rom_size, chr_rom_size)?;
+
+        Ok(Self {
+            prg_rom,
+            chr_rom,
+            trainer,
+            prg_ram_size: nes2_ram_size(prg_ram_shifts.volatile()),
+            prg_nvram_size: nes2_ram_size(prg_ram_shifts.non_volatile()),
+            chr_ram_size: nes2_ram_size(chr_ram_shifts.volatile()),
+            chr_nvram_size: nes2_ram_size(chr_ram_shifts.non_volatile()),
+            mapper,
+            submapper: mapper_msb.submapper(),
+            nametable_layout: flags6.nametable_layout(),
+            alternative_nametables: flags6.enable_alternative_nametables(),
+            region,
+            console_type: flags7.console_type(),
+            vs_ppu_type,
+            vs_hardware_type,
+            extended_console_type,
+            misc_rom_count,
+            default_expansion_device,
+        })
+    }
+}
+
+fn read_trainer<R: io::Read>(
+    reader: &mut R,
+    has_trainer: bool,
+) -> Result<Option<Vec<u8>>, RomError> {
+    if has_trainer {
+        read_rom(reader, 512)
+            .map(Some)
+            .map_err(|_| RomError::TruncatedHeader)
+    } else {
+        Ok(None)
+    }
+}
+
+fn read_roms<R: io::Read>(
+    reader: &mut R,
+    prg_rom_size: usize,
+    chr_rom_size: usize,
+) -> Result<(Vec<u8>, Vec<u8>), RomError> {
+    let prg_rom = read_rom(reader, prg_rom_size).map_err(|actual| RomError::TruncatedPrg {
+        expected: prg_rom_size,
+        actual,
+    })?;
+    let chr_rom = read_rom(reader, chr_rom_size).map_err(|actual| RomError::TruncatedChr {
+        expected: chr_rom_size,
+        actual,
+    })?;
+    Ok((prg_rom, chr_rom))
+}
+
+/// Reads `size` bytes, or reports how many were available.
+fn read_rom<R: io::Read>(reader: &mut R, size: usize) -> Result<Vec<u8>, usize> {
+    let mut rom = Vec::new();
+    let actual = reader
+        .take(size as u64)
+        .read_to_end(&mut rom)
+        .unwrap_or(rom.len());
+    if actual == size {
+        Ok(rom)
+    } else {
+        Err(actual)
+    }
+}
+
+/// Largest ROM the exponent-multiplier form may ask for; the bank-count form tops out just under it.
+const MAX_ROM_SIZE: usize = 64 * 1024 * 1024;
+
+/// ROM sizes are counted in `unit` sized banks, unless the MSB nibble is $F and the LSB holds an exponent-multiplier
+/// pair EEEEEEMM giving 2^E * (MM * 2 + 1) bytes.
+fn nes2_rom_size(lsb: u8, msb: u8, unit: usize) -> Result<usize, RomError> {
+    if msb == 0xF {
+        1usize
+            .checked_shl((lsb >> 2) as u32)
+            .and_then(|size| size.checked_mul((lsb & 0b11) as usize * 2 + 1))
+            .filter(|&size| size <= MAX_ROM_SIZE)
+            .ok_or(RomError::InconsistentHeader("ROM size out of range"))
+    } else {
+        Ok((((msb as usize) << 8) | lsb as usize) * unit)
+    }
+}
 
-        todo!()
+/// RAM sizes are shift counts, with 0 meaning none and anything else 64 << shift bytes.
+fn nes2_ram_size(shift: u8) -> usize {
+    match shift {
+        0 => 0,
+        shift => 64 << shift,
     }
 }
 
-pub fn ntsc_system<PrgMapper: BusDevice + Send + 'static, ChrMapper: BusDevice + Send + 'static>(
+pub fn ntsc_system<
+    PrgMapper: BusDevice + Send + 'static,
+    ChrMapper: NametableMapper + Send + 'static,
+>(
+    prg_mapper: PrgMapper,
+    chr_mapper: ChrMapper,
+) -> System<RP2A03, SystemBus<PrgMapper, ChrMapper>> {
+    region_system(Region::Ntsc, prg_mapper, chr_mapper)
+}
+
+pub fn pal_system<
+    PrgMapper: BusDevice + Send + 'static,
+    ChrMapper: NametableMapper + Send + 'static,
+>(
+    prg_mapper: PrgMapper,
+    chr_mapper: ChrMapper,
+) -> System<RP2A03, SystemBus<PrgMapper, ChrMapper>> {
+    region_system(Region::Pal, prg_mapper, chr_mapper)
+}
+
+pub fn dendy_system<
+    PrgMapper: BusDevice + Send + 'static,
+    ChrMapper: NametableMapper + Send + 'static,
+>(
+    prg_mapper: PrgMapper,
+    chr_mapper: ChrMapper,
+) -> System<RP2A03, SystemBus<PrgMapper, ChrMapper>> {
+    region_system(Region::Dendy, prg_mapper, chr_mapper)
+}
+
+pub fn region_system<
+    PrgMapper: BusDevice + Send + 'static,
+    ChrMapper: NametableMapper + Send + 'static,
+>(
+    region: Region,
+    prg_mapper: PrgMapper,
+    chr_mapper: ChrMapper,
+) -> System<RP2A03, SystemBus<PrgMapper, ChrMapper>> {
+    System::new(
+        RP2A03::new(),
+        SystemBus::new(prg_mapper, chr_mapper, region),
+    )
+}
+
+/// Builds a system for the region in the image header, unless `region_override` picks one by hand.
+pub fn system_for<
+    PrgMapper: BusDevice + Send + 'static,
+    ChrMapper: NametableMapper + Send + 'static,
+>(
+    rom_image: &RomImage,
+    region_override: Option<Region>,
     prg_mapper: PrgMapper,
     chr_mapper: ChrMapper,
 ) -> System<RP2A03, SystemBus<PrgMapper, ChrMapper>> {
-    System::new(RP2A03::new(), SystemBus::new(prg_mapper, chr_mapper))
+    region_system(
+        region_override.unwrap_or(rom_image.region),
+        prg_mapper,
+        chr_mapper,
+    )
 }
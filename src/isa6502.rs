@@ -5,6 +5,8 @@ use instructions::{Instruction, MicrocodeControl, ReadInstruction, WriteInstruct
 use crate::{Address, Bus};
 
 pub mod addressing;
+pub mod cmos;
+pub mod cpu;
 pub mod instructions;
 
 #[derive(Debug, Default)]
@@ -60,6 +62,7 @@ pub trait Decode: MicrocodeControl + AddressMode {
         Self: Sized;
     fn queue_branch(&mut self);
     fn queue_brk(&mut self);
+    fn queue_interrupt(&mut self);
     fn queue_jsr(&mut self);
     fn queue_rti(&mut self);
     fn queue_rts(&mut self);
@@ -96,6 +99,17 @@ impl StatusFlags {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuState {
+    Running,
+    /// Stopped by WAI until an interrupt line is asserted.
+    WaitingForInterrupt,
+    /// Stopped by the given opcode until reset.
+    Halted(u8),
+    /// Stalled while a DMA unit owns the bus.
+    WaitingForDma,
+}
+
 pub trait AddressMode {
     fn address(&mut self) -> Address;
     fn pc(&mut self) -> Address;
@@ -111,6 +125,13 @@ pub trait Cpu
 where
     Self: Sized,
 {
-    const CLOCK_DIVISOR: u64;
     fn cycle(&mut self, bus: &mut impl Bus);
+    fn set_nmi(&mut self, asserted: bool);
+    fn set_irq(&mut self, asserted: bool);
+    fn set_dma_halt(&mut self, asserted: bool);
+    fn state(&self) -> CpuState;
+    /// Puts the registers in their power-up state and runs the reset sequence.
+    fn power_on(&mut self);
+    /// Asserts the reset line, which only affects S, P and the program counter.
+    fn reset(&mut self);
 }
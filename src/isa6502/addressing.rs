@@ -111,7 +111,7 @@ impl<CPU: MicrocodeControl + AddressMode + MicrocodeInstructions, INST: ReadWrit
             BusDirection::Read(CPU::buffer_high),
         );
         cpu.queue_microcode(CPU::address, BusDirection::Read(CPU::nop));
-        cpu.queue_microcode(CPU::address, BusDirection::Write(CPU::nop));
+        cpu.queue_modify(CPU::address);
         cpu.queue_read_write::<INST>(CPU::address);
         cpu.queue_decode();
     }
@@ -154,7 +154,7 @@ impl<CPU: MicrocodeControl + AddressMode + MicrocodeInstructions, INST: ReadWrit
     fn enqueue(cpu: &mut CPU) {
         cpu.queue_microcode(CPU::pc_inc, BusDirection::Read(CPU::pull_operand));
         cpu.queue_microcode(CPU::zeropage, BusDirection::Read(CPU::nop));
-        cpu.queue_microcode(CPU::zeropage, BusDirection::Write(CPU::nop));
+        cpu.queue_modify(CPU::zeropage);
         cpu.queue_read_write::<INST>(CPU::zeropage);
         cpu.queue_decode();
     }
@@ -285,7 +285,7 @@ impl<CPU: MicrocodeControl + AddressMode + MicrocodeInstructions, INST: ReadWrit
         cpu.queue_microcode(CPU::pc_inc, BusDirection::Read(CPU::buffer_low));
         cpu.queue_microcode(CPU::pc_inc, BusDirection::Read(CPU::buffer_high));
         cpu.queue_microcode(CPU::address, BusDirection::Read(CPU::nop));
-        cpu.queue_microcode(CPU::address, BusDirection::Write(CPU::nop));
+        cpu.queue_modify(CPU::address);
         cpu.queue_read_write::<INST>(CPU::address);
         cpu.queue_decode();
     }
@@ -367,7 +367,7 @@ impl<CPU: MicrocodeControl + AddressMode + MicrocodeInstructions, INST: ReadWrit
             BusDirection::Read(CPU::nop),
         );
         cpu.queue_microcode(Self::address_indexed_y, BusDirection::Read(CPU::nop));
-        cpu.queue_microcode(Self::address_indexed_y, BusDirection::Write(CPU::nop));
+        cpu.queue_modify(Self::address_indexed_y);
         cpu.queue_read_write::<INST>(Self::address_indexed_y);
         cpu.queue_decode();
     }
@@ -379,7 +379,7 @@ impl<CPU: MicrocodeControl + AddressMode + MicrocodeInstructions, INST: WriteIns
     fn enqueue(cpu: &mut CPU) {
         cpu.queue_microcode(CPU::pc_inc, BusDirection::Read(CPU::pull_operand));
         cpu.queue_microcode(CPU::zeropage, BusDirection::Read(CPU::buffer_low));
-        cpu.queue_microcode(Self::zeropage_high, BusDirection::Read(CPU::nop));
+        cpu.queue_microcode(Self::zeropage_high, BusDirection::Read(CPU::buffer_high));
         cpu.queue_microcode(
             Self::address_indexed_y_no_carry,
             BusDirection::Read(CPU::nop),
@@ -389,6 +389,39 @@ impl<CPU: MicrocodeControl + AddressMode + MicrocodeInstructions, INST: WriteIns
     }
 }
 
+pub struct ZeroPageIndirect;
+impl ZeroPageIndirect {
+    fn zeropage_high<CPU: MicrocodeControl + AddressMode + MicrocodeInstructions>(
+        cpu: &mut CPU,
+    ) -> Address {
+        cpu.zeropage().index(1)
+    }
+}
+
+impl<CPU: MicrocodeControl + AddressMode + MicrocodeInstructions, INST: ReadInstruction>
+    AddressingMode<CPU, INST, Read> for ZeroPageIndirect
+{
+    fn enqueue(cpu: &mut CPU) {
+        cpu.queue_microcode(CPU::pc_inc, BusDirection::Read(CPU::pull_operand));
+        cpu.queue_microcode(CPU::zeropage, BusDirection::Read(CPU::buffer_low));
+        cpu.queue_microcode(Self::zeropage_high, BusDirection::Read(CPU::buffer_high));
+        cpu.queue_read::<INST>(CPU::address);
+        cpu.queue_decode();
+    }
+}
+
+impl<CPU: MicrocodeControl + AddressMode + MicrocodeInstructions, INST: WriteInstruction>
+    AddressingMode<CPU, INST, Write> for ZeroPageIndirect
+{
+    fn enqueue(cpu: &mut CPU) {
+        cpu.queue_microcode(CPU::pc_inc, BusDirection::Read(CPU::pull_operand));
+        cpu.queue_microcode(CPU::zeropage, BusDirection::Read(CPU::buffer_low));
+        cpu.queue_microcode(Self::zeropage_high, BusDirection::Read(CPU::buffer_high));
+        cpu.queue_write::<INST>(CPU::address);
+        cpu.queue_decode();
+    }
+}
+
 pub struct ZeroPageIndexed<const INDEX_X: bool>;
 
 impl<const INDEX_X: bool> ZeroPageIndexed<INDEX_X> {
@@ -427,7 +460,7 @@ impl<
         cpu.queue_microcode(CPU::pc_inc, BusDirection::Read(CPU::pull_operand));
         cpu.queue_microcode(CPU::zeropage, BusDirection::Read(CPU::nop));
         cpu.queue_microcode(Self::zeropage_indexed, BusDirection::Read(CPU::nop));
-        cpu.queue_microcode(Self::zeropage_indexed, BusDirection::Write(CPU::nop));
+        cpu.queue_modify(Self::zeropage_indexed);
         cpu.queue_read_write::<INST>(Self::zeropage_indexed);
         cpu.queue_decode();
     }
@@ -557,10 +590,7 @@ impl<
             Self::address_indexed_corrected,
             BusDirection::Read(CPU::nop),
         );
-        cpu.queue_microcode(
-            Self::address_indexed_corrected,
-            BusDirection::Write(CPU::nop),
-        );
+        cpu.queue_modify(Self::address_indexed_corrected);
         cpu.queue_read_write::<INST>(Self::address_indexed_corrected);
         cpu.queue_decode();
     }
@@ -580,3 +610,76 @@ impl<
         cpu.queue_decode();
     }
 }
+
+// The 65C02 only spends a cycle fixing the high byte of ASL, LSR, ROL and ROR abs,X when the index crosses a page
+pub struct AbsoluteIndexedShift;
+
+impl<CPU: MicrocodeControl + AddressMode + MicrocodeInstructions, INST: ReadWriteInstruction>
+    AddressingMode<CPU, INST, ReadWrite> for AbsoluteIndexedShift
+{
+    fn enqueue(cpu: &mut CPU) {
+        cpu.queue_microcode(CPU::pc_inc, BusDirection::Read(CPU::buffer_low));
+        cpu.queue_microcode(
+            CPU::pc_inc,
+            BusDirection::Read(|cpu| {
+                cpu.buffer_high();
+                let address = cpu.address();
+                if address.index(cpu.index_x()) != address + cpu.index_x() {
+                    cpu.push_microcode(
+                        AbsoluteIndexed::<true>::address_indexed,
+                        BusDirection::Read(CPU::nop),
+                    );
+                }
+            }),
+        );
+        cpu.queue_microcode(
+            AbsoluteIndexed::<true>::address_indexed_corrected,
+            BusDirection::Read(CPU::nop),
+        );
+        cpu.queue_modify(AbsoluteIndexed::<true>::address_indexed_corrected);
+        cpu.queue_read_write::<INST>(AbsoluteIndexed::<true>::address_indexed_corrected);
+        cpu.queue_decode();
+    }
+}
+
+// SHA, SHX, SHY and TAS compute their value and final address during the dummy read, then store to it
+pub struct AbsoluteIndexedUnstable<const INDEX_X: bool>;
+
+impl<
+        CPU: MicrocodeControl + AddressMode + MicrocodeInstructions,
+        INST: WriteInstruction,
+        const INDEX_X: bool,
+    > AddressingMode<CPU, INST, Write> for AbsoluteIndexedUnstable<INDEX_X>
+{
+    fn enqueue(cpu: &mut CPU) {
+        cpu.queue_microcode(CPU::pc_inc, BusDirection::Read(CPU::buffer_low));
+        cpu.queue_microcode(CPU::pc_inc, BusDirection::Read(CPU::buffer_high));
+        cpu.queue_microcode(
+            AbsoluteIndexed::<INDEX_X>::address_indexed,
+            BusDirection::Read(CPU::write_instruction::<INST>),
+        );
+        cpu.queue_microcode(CPU::address, BusDirection::Write(CPU::nop));
+        cpu.queue_decode();
+    }
+}
+
+pub struct IndirectIndexedYUnstable;
+
+impl<CPU: MicrocodeControl + AddressMode + MicrocodeInstructions, INST: WriteInstruction>
+    AddressingMode<CPU, INST, Write> for IndirectIndexedYUnstable
+{
+    fn enqueue(cpu: &mut CPU) {
+        cpu.queue_microcode(CPU::pc_inc, BusDirection::Read(CPU::pull_operand));
+        cpu.queue_microcode(CPU::zeropage, BusDirection::Read(CPU::buffer_low));
+        cpu.queue_microcode(
+            IndirectIndexedY::zeropage_high,
+            BusDirection::Read(CPU::buffer_high),
+        );
+        cpu.queue_microcode(
+            IndirectIndexedY::address_indexed_y_no_carry,
+            BusDirection::Read(CPU::write_instruction::<INST>),
+        );
+        cpu.queue_microcode(CPU::address, BusDirection::Write(CPU::nop));
+        cpu.queue_decode();
+    }
+}
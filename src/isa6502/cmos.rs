@@ -0,0 +1,393 @@
+use crate::{Address, BusDirection};
+
+use super::{
+    addressing::*,
+    cpu::{Cpu6502, Variant},
+    instructions::{self, *},
+    AddressMode, CpuState, Decode, MicrocodeInstructions, Registers, StatusFlags,
+};
+
+/// The WDC 65C02, including the Rockwell bit manipulation instructions.
+#[derive(Debug)]
+pub struct Wdc65C02;
+
+impl Variant for Wdc65C02 {
+    const CMOS: bool = true;
+
+    fn decode(cpu: &mut Cpu6502<Self>) -> fn(&mut Cpu6502<Self>) {
+        cpu.decode_cmos()
+    }
+}
+
+pub type W65C02 = Cpu6502<Wdc65C02>;
+
+impl W65C02 {
+    fn decode_cmos(&mut self) -> fn(&mut Self) {
+        // 0000_0000
+        // bit 7-5: row
+        // bit 4-0: column
+        // bit 1-0: block
+        let row = (self.opcode & 0b1110_0000) >> 4;
+        let column = self.opcode & 0b0001_1111;
+        let block = self.opcode & 0b0000_0011;
+
+        if column == 0x10 || self.opcode == 0x80 {
+            return Self::queue_branch;
+        }
+
+        match (row, column, block) {
+            // Control
+            (0x0, 0x0, _) => Self::queue_brk,
+            (0x2, 0x0, _) => Self::queue_jsr,
+            (0x4, 0x0, _) => Self::queue_rti,
+            (0x6, 0x0, _) => Self::queue_rts,
+            (0x0, 0x4, _) => self.decode_addressing::<TSB, ReadWrite>(row, column),
+            (0x0, 0xC, _) => self.decode_addressing::<TSB, ReadWrite>(row, column),
+            (0x0, 0x14, _) => Self::addressing::<ZeroPage, TRB, ReadWrite>,
+            (0x0, 0x1C, _) => Self::addressing::<Absolute, TRB, ReadWrite>,
+            (0x2, 0x4 | 0xC | 0x14 | 0x1C, _) => self.decode_addressing::<BIT, Read>(row, column),
+            (0x4, 0xC, _) => Self::queue_jmp,
+            (0x6, 0xC, _) => Self::queue_cmos_indirect_jmp,
+            (0x6, 0x1C, _) => Self::queue_indexed_indirect_jmp,
+            (0x6, 0x4 | 0x14, _) => self.decode_addressing::<STZ, Write>(row, column),
+            (0x8, 0x1C, _) => Self::addressing::<Absolute, STZ, Write>,
+            (0x8, 0x1E, _) => Self::addressing::<AbsoluteIndexed<true>, STZ, Write>,
+            (0x4, 0x1C, _) => Self::queue_long_nop,
+            (0xC | 0xE, 0x1C, _) => Self::addressing::<Absolute, NOP, Read>,
+            (0x4 | 0xC | 0xE, 0x14, _) => self.decode_addressing::<NOP, Read>(row, column),
+            (0x0, 0x8, _) => self.decode_addressing::<PHP, Write>(row, column),
+            (0x2, 0x8, _) => self.decode_addressing::<PLP, Read>(row, column),
+            (0x4, 0x8, _) => self.decode_addressing::<PHA, Write>(row, column),
+            (0x6, 0x8, _) => self.decode_addressing::<PLA, Read>(row, column),
+            (0x8, 0x8, _) => self.decode_addressing::<DEY, Read>(row, column),
+            (0xA, 0x8, _) => self.decode_addressing::<TAY, Read>(row, column),
+            (0xC, 0x8, _) => self.decode_addressing::<INY, Read>(row, column),
+            (0xE, 0x8, _) => self.decode_addressing::<INX, Read>(row, column),
+            (0x0, 0x18, _) => self.decode_addressing::<CLC, Read>(row, column),
+            (0x2, 0x18, _) => self.decode_addressing::<SEC, Read>(row, column),
+            (0x4, 0x18, _) => self.decode_addressing::<CLI, Read>(row, column),
+            (0x6, 0x18, _) => self.decode_addressing::<SEI, Read>(row, column),
+            (0x8, 0x18, _) => self.decode_addressing::<TYA, Read>(row, column),
+            (0xA, 0x18, _) => self.decode_addressing::<CLV, Read>(row, column),
+            (0xC, 0x18, _) => self.decode_addressing::<CLD, Read>(row, column),
+            (0xE, 0x18, _) => self.decode_addressing::<SED, Read>(row, column),
+
+            (0x8, _, 0) => self.decode_addressing::<STY, Write>(row, column),
+            (0xA, _, 0) => self.decode_addressing::<LDY, Read>(row, column),
+            (0xC, _, 0) => self.decode_addressing::<CPY, Read>(row, column),
+            (0xE, _, 0) => self.decode_addressing::<CPX, Read>(row, column),
+            (_, _, 0) => self.decode_addressing::<NOP, Read>(row, column),
+
+            // ALU
+            (0x8, 0x9, _) => self.decode_addressing::<BitImmediate, Read>(row, column),
+            (0x0, _, 1) => self.decode_addressing::<ORA, Read>(row, column),
+            (0x2, _, 1) => self.decode_addressing::<AND, Read>(row, column),
+            (0x4, _, 1) => self.decode_addressing::<EOR, Read>(row, column),
+            (0x6, _, 1) => Self::queue_decimal::<ADC>,
+            (0x8, _, 1) => self.decode_addressing::<STA, Write>(row, column),
+            (0xA, _, 1) => self.decode_addressing::<LDA, Read>(row, column),
+            (0xC, _, 1) => self.decode_addressing::<CMP, Read>(row, column),
+            (0xE, _, 1) => Self::queue_decimal::<SBC>,
+
+            // Zero page indirect shares the ALU rows
+            (0x0, 0x12, _) => Self::addressing::<ZeroPageIndirect, ORA, Read>,
+            (0x2, 0x12, _) => Self::addressing::<ZeroPageIndirect, AND, Read>,
+            (0x4, 0x12, _) => Self::addressing::<ZeroPageIndirect, EOR, Read>,
+            (0x6, 0x12, _) => Self::queue_decimal::<ADC>,
+            (0x8, 0x12, _) => Self::addressing::<ZeroPageIndirect, STA, Write>,
+            (0xA, 0x12, _) => Self::addressing::<ZeroPageIndirect, LDA, Read>,
+            (0xC, 0x12, _) => Self::addressing::<ZeroPageIndirect, CMP, Read>,
+            (0xE, 0x12, _) => Self::queue_decimal::<SBC>,
+
+            // RMW
+            (0xA, 0x2, _) => self.decode_addressing::<LDX, Read>(row, column),
+            (_, 0x2, _) => self.decode_addressing::<NOP, Read>(row, column),
+            (0x0, 0x1A, _) => Self::addressing::<Accumulator, INC, ReadWrite>,
+            (0x2, 0x1A, _) => Self::addressing::<Accumulator, DEC, ReadWrite>,
+            (0x4, 0x1A, _) => Self::addressing::<Stack, PHY, Write>,
+            (0x6, 0x1A, _) => Self::addressing::<Stack, PLY, Read>,
+            (0xC, 0x1A, _) => Self::addressing::<Stack, PHX, Write>,
+            (0xE, 0x1A, _) => Self::addressing::<Stack, PLX, Read>,
+            (0x0, 0x1E, _) => Self::addressing::<AbsoluteIndexedShift, ASL, ReadWrite>,
+            (0x2, 0x1E, _) => Self::addressing::<AbsoluteIndexedShift, ROL, ReadWrite>,
+            (0x4, 0x1E, _) => Self::addressing::<AbsoluteIndexedShift, LSR, ReadWrite>,
+            (0x6, 0x1E, _) => Self::addressing::<AbsoluteIndexedShift, ROR, ReadWrite>,
+            (0x0, _, 2) => self.decode_addressing::<ASL, ReadWrite>(row, column),
+            (0x2, _, 2) => self.decode_addressing::<ROL, ReadWrite>(row, column),
+            (0x4, _, 2) => self.decode_addressing::<LSR, ReadWrite>(row, column),
+            (0x6, _, 2) => self.decode_addressing::<ROR, ReadWrite>(row, column),
+            (0x8, 0xA, _) => self.decode_addressing::<TXA, Read>(row, column),
+            (0x8, 0x1A, _) => self.decode_addressing::<TXS, Read>(row, column),
+            (0x8, _, 2) => self.decode_addressing::<STX, Write>(row, column),
+            (0xA, 0xA, _) => self.decode_addressing::<TAX, Read>(row, column),
+            (0xA, 0x1A, _) => self.decode_addressing::<TSX, Read>(row, column),
+            (0xA, _, 2) => self.decode_addressing::<LDX, Read>(row, column),
+            (0xC, 0xA, _) => self.decode_addressing::<DEX, Read>(row, column),
+            (0xC, _, 2) => self.decode_addressing::<DEC, ReadWrite>(row, column),
+            (0xE, 0xA, _) => self.decode_addressing::<NOP, Read>(row, column),
+            (0xE, _, 2) => self.decode_addressing::<INC, ReadWrite>(row, column),
+
+            // Rockwell bit manipulation and the single cycle NOPs
+            (0xC, 0xB, _) => Self::queue_wai,
+            (0xC, 0x1B, _) => Self::queue_stp,
+            (_, 0x7 | 0x17 | 0xF | 0x1F, _) => self.decode_bit_manipulation(),
+            (_, _, 3) => Self::queue_decode,
+            _ => Self::queue_jam,
+        }
+    }
+
+    fn decode_bit_manipulation(&self) -> fn(&mut Self) {
+        match self.opcode {
+            0x07 => Self::addressing::<ZeroPage, RMB<0>, ReadWrite>,
+            0x17 => Self::addressing::<ZeroPage, RMB<1>, ReadWrite>,
+            0x27 => Self::addressing::<ZeroPage, RMB<2>, ReadWrite>,
+            0x37 => Self::addressing::<ZeroPage, RMB<3>, ReadWrite>,
+            0x47 => Self::addressing::<ZeroPage, RMB<4>, ReadWrite>,
+            0x57 => Self::addressing::<ZeroPage, RMB<5>, ReadWrite>,
+            0x67 => Self::addressing::<ZeroPage, RMB<6>, ReadWrite>,
+            0x77 => Self::addressing::<ZeroPage, RMB<7>, ReadWrite>,
+            0x87 => Self::addressing::<ZeroPage, SMB<0>, ReadWrite>,
+            0x97 => Self::addressing::<ZeroPage, SMB<1>, ReadWrite>,
+            0xA7 => Self::addressing::<ZeroPage, SMB<2>, ReadWrite>,
+            0xB7 => Self::addressing::<ZeroPage, SMB<3>, ReadWrite>,
+            0xC7 => Self::addressing::<ZeroPage, SMB<4>, ReadWrite>,
+            0xD7 => Self::addressing::<ZeroPage, SMB<5>, ReadWrite>,
+            0xE7 => Self::addressing::<ZeroPage, SMB<6>, ReadWrite>,
+            0xF7 => Self::addressing::<ZeroPage, SMB<7>, ReadWrite>,
+            0x0F => Self::queue_bit_branch::<0, false>,
+            0x1F => Self::queue_bit_branch::<1, false>,
+            0x2F => Self::queue_bit_branch::<2, false>,
+            0x3F => Self::queue_bit_branch::<3, false>,
+            0x4F => Self::queue_bit_branch::<4, false>,
+            0x5F => Self::queue_bit_branch::<5, false>,
+            0x6F => Self::queue_bit_branch::<6, false>,
+            0x7F => Self::queue_bit_branch::<7, false>,
+            0x8F => Self::queue_bit_branch::<0, true>,
+            0x9F => Self::queue_bit_branch::<1, true>,
+            0xAF => Self::queue_bit_branch::<2, true>,
+            0xBF => Self::queue_bit_branch::<3, true>,
+            0xCF => Self::queue_bit_branch::<4, true>,
+            0xDF => Self::queue_bit_branch::<5, true>,
+            0xEF => Self::queue_bit_branch::<6, true>,
+            0xFF => Self::queue_bit_branch::<7, true>,
+            _ => unreachable!("{:02X}", self.opcode),
+        }
+    }
+
+    // BBR/BBS: test a zero page bit, then branch like any other relative branch
+    fn queue_bit_branch<const N: u8, const SET: bool>(&mut self) {
+        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::pull_operand));
+        self.queue_microcode(Self::zeropage, BusDirection::Read(Self::buffer_low));
+        self.queue_microcode(Self::zeropage, BusDirection::Read(Self::nop));
+        self.queue_microcode(
+            Self::pc_inc,
+            BusDirection::Read(|cpu| {
+                cpu.pull_operand();
+                let bit_set = cpu.registers.address_buffer.low() & (1 << N) != 0;
+                if bit_set == SET {
+                    cpu.push_microcode(Self::pc, BusDirection::Read(Self::branch));
+                }
+            }),
+        );
+        self.queue_decode();
+    }
+
+    // ADC and SBC take a cycle longer in decimal mode, reading the next opcode address again while the result is
+    // corrected
+    fn queue_decimal<INST: ReadInstruction>(&mut self) {
+        let row = (self.opcode & 0b1110_0000) >> 4;
+        let column = self.opcode & 0b0001_1111;
+        let enqueue = match column {
+            0x12 => Self::addressing::<ZeroPageIndirect, INST, Read>,
+            _ => self.decode_addressing::<INST, Read>(row, column),
+        };
+        enqueue(self);
+
+        if self.registers.p.contains(StatusFlags::D) {
+            self.queue_before_decode(Self::pc, BusDirection::Read(Self::nop));
+        }
+    }
+
+    // Unlike the NMOS part, the pointer high byte is read with carry into the next page
+    fn queue_cmos_indirect_jmp(&mut self) {
+        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::buffer_low));
+        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::buffer_high));
+        self.queue_microcode(Self::previous_pc, BusDirection::Read(Self::nop));
+        self.queue_microcode(Self::address, BusDirection::Read(Self::pull_operand));
+        self.queue_read::<JMP>(|cpu| cpu.address() + 1);
+        self.queue_decode();
+    }
+
+    fn queue_indexed_indirect_jmp(&mut self) {
+        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::buffer_low));
+        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::buffer_high));
+        self.queue_microcode(Self::previous_pc, BusDirection::Read(Self::nop));
+        self.queue_microcode(
+            |cpu| cpu.address() + cpu.index_x(),
+            BusDirection::Read(Self::pull_operand),
+        );
+        self.queue_read::<JMP>(|cpu| cpu.address() + cpu.index_x() + 1);
+        self.queue_decode();
+    }
+
+    // $5C reads its operand and then spends five more cycles on the bus
+    fn queue_long_nop(&mut self) {
+        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::buffer_low));
+        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::buffer_high));
+        for _ in 0..5 {
+            self.queue_microcode(Self::address, BusDirection::Read(Self::nop));
+        }
+        self.queue_decode();
+    }
+
+    fn queue_wai(&mut self) {
+        self.queue_microcode(Self::pc, BusDirection::Read(Self::nop));
+        self.queue_microcode(
+            Self::pc,
+            BusDirection::Read(|cpu| cpu.state = CpuState::WaitingForInterrupt),
+        );
+        self.queue_decode();
+    }
+
+    fn queue_stp(&mut self) {
+        self.queue_microcode(Self::pc, BusDirection::Read(Self::nop));
+        self.queue_microcode(
+            Self::pc,
+            BusDirection::Read(|cpu| cpu.state = CpuState::Halted(cpu.opcode)),
+        );
+        self.queue_decode();
+    }
+
+    fn previous_pc(&mut self) -> Address {
+        let mut address = self.pc();
+        address.offset(-1);
+        address
+    }
+}
+
+// 65C02 decimal mode produces valid N and Z flags, following Bruce Clark's "Decimal Mode" appendix A
+pub struct ADC;
+impl ReadInstruction for ADC {
+    fn execute(registers: &mut Registers, data: &u8) {
+        if !registers.p.contains(StatusFlags::D) {
+            instructions::ADC::<false>::execute(registers, data);
+            return;
+        }
+
+        let carry = registers.p.bits() & 1;
+        let mut low = (registers.a & 0x0F) as u16 + (*data & 0x0F) as u16 + carry as u16;
+        if low >= 0x0A {
+            low = ((low + 0x06) & 0x0F) + 0x10;
+        }
+
+        let signed = (registers.a & 0xF0) as i8 as i16 + (*data & 0xF0) as i8 as i16 + low as i16;
+        registers
+            .p
+            .set(StatusFlags::V, !(-128..=127).contains(&signed));
+
+        let mut sum = (registers.a & 0xF0) as u16 + (*data & 0xF0) as u16 + low;
+        if sum >= 0xA0 {
+            sum += 0x60;
+        }
+        registers.p.set(StatusFlags::C, sum >= 0x100);
+        registers.a = sum as u8;
+        registers.p.set_value_flags(registers.a);
+    }
+}
+
+pub struct SBC;
+impl ReadInstruction for SBC {
+    fn execute(registers: &mut Registers, data: &u8) {
+        let a = registers.a;
+        let carry = registers.p.bits() & 1;
+        instructions::SBC::<false>::execute(registers, data);
+
+        if registers.p.contains(StatusFlags::D) {
+            let low = (a & 0x0F) as i16 - (*data & 0x0F) as i16 + carry as i16 - 1;
+            let mut difference = a as i16 - *data as i16 + carry as i16 - 1;
+            if difference < 0 {
+                difference -= 0x60;
+            }
+            if low < 0 {
+                difference -= 0x06;
+            }
+            registers.a = difference as u8;
+            registers.p.set_value_flags(registers.a);
+        }
+    }
+}
+
+// BIT #imm only affects Z
+pub struct BitImmediate;
+impl ReadInstruction for BitImmediate {
+    fn execute(registers: &mut Registers, data: &u8) {
+        registers.p.set(StatusFlags::Z, registers.a & data == 0);
+    }
+}
+
+pub struct STZ;
+impl WriteInstruction for STZ {
+    fn execute(_: &mut Registers, data: &mut u8) {
+        *data = 0;
+    }
+}
+
+pub struct TSB;
+impl ReadWriteInstruction for TSB {
+    fn execute(registers: &mut Registers, data: &mut u8) {
+        registers.p.set(StatusFlags::Z, registers.a & *data == 0);
+        *data |= registers.a;
+    }
+}
+
+pub struct TRB;
+impl ReadWriteInstruction for TRB {
+    fn execute(registers: &mut Registers, data: &mut u8) {
+        registers.p.set(StatusFlags::Z, registers.a & *data == 0);
+        *data &= !registers.a;
+    }
+}
+
+pub struct PHX;
+impl WriteInstruction for PHX {
+    fn execute(registers: &mut Registers, data: &mut u8) {
+        *data = registers.x;
+    }
+}
+
+pub struct PHY;
+impl WriteInstruction for PHY {
+    fn execute(registers: &mut Registers, data: &mut u8) {
+        *data = registers.y;
+    }
+}
+
+pub struct PLX;
+impl ReadInstruction for PLX {
+    fn execute(registers: &mut Registers, data: &u8) {
+        registers.x = *data;
+        registers.p.set_value_flags(registers.x);
+    }
+}
+
+pub struct PLY;
+impl ReadInstruction for PLY {
+    fn execute(registers: &mut Registers, data: &u8) {
+        registers.y = *data;
+        registers.p.set_value_flags(registers.y);
+    }
+}
+
+pub struct RMB<const N: u8>;
+impl<const N: u8> ReadWriteInstruction for RMB<N> {
+    fn execute(_: &mut Registers, data: &mut u8) {
+        *data &= !(1 << N);
+    }
+}
+
+pub struct SMB<const N: u8>;
+impl<const N: u8> ReadWriteInstruction for SMB<N> {
+    fn execute(_: &mut Registers, data: &mut u8) {
+        *data |= 1 << N;
+    }
+}
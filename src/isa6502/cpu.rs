@@ -0,0 +1,720 @@
+use core::marker::PhantomData;
+use std::collections::VecDeque;
+
+use crate::{Address, Bus};
+
+use super::{addressing::*, instructions::*, *};
+
+type Microcode<CPU> = (fn(&mut CPU) -> Address, BusDirection<CPU>);
+
+/// Selects the behaviour of a [`Cpu6502`] that differs between the chips built around the 6502 core.
+pub trait Variant: Sized {
+    /// CMOS parts clear D on interrupts and spend the modify cycle of read-modify-write instructions on a read.
+    const CMOS: bool = false;
+
+    /// Decodes `cpu.opcode` into the function that queues its microcode.
+    fn decode(cpu: &mut Cpu6502<Self>) -> fn(&mut Cpu6502<Self>);
+}
+
+/// The original NMOS 6502, with decimal mode ADC/SBC.
+#[derive(Debug)]
+pub struct Nmos;
+
+impl Variant for Nmos {
+    fn decode(cpu: &mut Cpu6502<Self>) -> fn(&mut Cpu6502<Self>) {
+        cpu.decode_nmos::<true>()
+    }
+}
+
+pub type Mos6502 = Cpu6502<Nmos>;
+
+#[derive(Debug)]
+pub struct Cpu6502<V: Variant> {
+    pub(crate) registers: Registers,
+    decode_cache: [Option<fn(&mut Self)>; 256],
+    timing: VecDeque<Microcode<Self>>,
+    pub(crate) opcode: u8,
+    data_latch: u8,
+    pub(crate) cycles: u64,
+    nmi_line: bool,
+    nmi_detected: bool,
+    irq_line: bool,
+    interrupt_pending: bool,
+    skip_poll: bool,
+    dma_halt: bool,
+    halted_address: Address,
+    pub(crate) state: CpuState,
+    variant: PhantomData<V>,
+}
+
+impl<V: Variant> MicrocodeControl for Cpu6502<V> {
+    fn push_microcode(
+        &mut self,
+        address_mode: fn(&mut Self) -> Address,
+        bus_mode: BusDirection<Self>,
+    ) {
+        self.timing.push_front((address_mode, bus_mode));
+    }
+
+    fn queue_microcode(
+        &mut self,
+        address_mode: fn(&mut Self) -> Address,
+        bus_mode: BusDirection<Self>,
+    ) {
+        self.timing.push_back((address_mode, bus_mode));
+    }
+
+    fn queue_decode(&mut self) {
+        self.queue_microcode(Self::fetch_opcode, BusDirection::Read(Self::decode_opcode));
+    }
+
+    fn clear_microcode(&mut self) {
+        self.timing.clear();
+    }
+
+    fn queue_read<INST: ReadInstruction>(&mut self, address_mode: fn(&mut Self) -> Address) {
+        self.queue_microcode(
+            address_mode,
+            BusDirection::Read(|cpu| INST::execute(&mut cpu.registers, &cpu.data_latch)),
+        );
+    }
+
+    fn queue_read_write<INST: ReadWriteInstruction>(
+        &mut self,
+        address_mode: fn(&mut Self) -> Address,
+    ) {
+        self.queue_microcode(
+            address_mode,
+            BusDirection::Write(|cpu| INST::execute(&mut cpu.registers, &mut cpu.data_latch)),
+        );
+    }
+
+    fn queue_write<INST: WriteInstruction>(&mut self, address_mode: fn(&mut Self) -> Address) {
+        self.queue_microcode(
+            address_mode,
+            BusDirection::Write(|cpu| INST::execute(&mut cpu.registers, &mut cpu.data_latch)),
+        );
+    }
+
+    fn queue_modify(&mut self, address_mode: fn(&mut Self) -> Address) {
+        if V::CMOS {
+            self.queue_microcode(address_mode, BusDirection::Read(Self::nop));
+        } else {
+            self.queue_microcode(address_mode, BusDirection::Write(Self::nop));
+        }
+    }
+}
+
+impl<V: Variant> AddressMode for Cpu6502<V> {
+    fn address(&mut self) -> Address {
+        self.registers.address_buffer
+    }
+
+    fn pc(&mut self) -> Address {
+        self.registers.pc
+    }
+
+    fn pc_inc(&mut self) -> Address {
+        let address = self.pc();
+        self.registers.pc.increment();
+        address
+    }
+
+    fn stack(&mut self) -> Address {
+        Address(0x100 | self.registers.stack as u16)
+    }
+
+    fn stack_push(&mut self) -> Address {
+        let address = self.stack();
+        self.registers.stack = self.registers.stack.wrapping_sub(1);
+        address
+    }
+
+    fn stack_pull(&mut self) -> Address {
+        self.registers.stack = self.registers.stack.wrapping_add(1);
+        self.stack()
+    }
+
+    fn vector<const VECTOR: u8>(&mut self) -> Address {
+        Address(0xFF00 | VECTOR as u16)
+    }
+
+    fn zeropage(&mut self) -> Address {
+        Address(self.registers.operand as u16)
+    }
+}
+
+impl<V: Variant> Decode for Cpu6502<V> {
+    fn decode_opcode(&mut self) {
+        if self.interrupt_pending {
+            // The fetched opcode is discarded and replaced with BRK
+            self.opcode = 0x00;
+            self.queue_interrupt();
+            return;
+        }
+
+        self.opcode = self.data_latch;
+
+        if let Some(enqueue) = self.decode_cache[self.opcode as usize] {
+            enqueue(self);
+            return;
+        }
+
+        let enqueue_timing = V::decode(self);
+        enqueue_timing(self);
+        self.decode_cache[self.opcode as usize] = Some(enqueue_timing);
+    }
+
+    fn decode_addressing<INST: Instruction<IO>, IO: IOMode>(
+        &mut self,
+        row: u8,
+        column: u8,
+    ) -> fn(&mut Self)
+    where
+        Immediate: AddressingMode<Self, INST, IO>,
+        IndexedIndirectX: AddressingMode<Self, INST, IO>,
+        ZeroPage: AddressingMode<Self, INST, IO>,
+        Stack: AddressingMode<Self, INST, IO>,
+        Accumulator: AddressingMode<Self, INST, IO>,
+        Absolute: AddressingMode<Self, INST, IO>,
+        IndirectIndexedY: AddressingMode<Self, INST, IO>,
+        ZeroPageIndexed<true>: AddressingMode<Self, INST, IO>,
+        ZeroPageIndexed<false>: AddressingMode<Self, INST, IO>,
+        Implied: AddressingMode<Self, INST, IO>,
+        AbsoluteIndexed<true>: AddressingMode<Self, INST, IO>,
+        AbsoluteIndexed<false>: AddressingMode<Self, INST, IO>,
+    {
+        match column {
+            0x00 | 0x02 => Self::addressing::<Immediate, INST, IO>,
+            0x01 | 0x03 => Self::addressing::<IndexedIndirectX, INST, IO>,
+            0x04..=0x07 => Self::addressing::<ZeroPage, INST, IO>,
+            0x08 => match row {
+                0x0..=0x6 => Self::addressing::<Stack, INST, IO>,
+                _ => Self::addressing::<Accumulator, INST, IO>,
+            },
+            0x0A => Self::addressing::<Accumulator, INST, IO>,
+            0x09 | 0x0B => Self::addressing::<Immediate, INST, IO>,
+            0x0C..=0x0F => Self::addressing::<Absolute, INST, IO>,
+            0x11 | 0x13 => Self::addressing::<IndirectIndexedY, INST, IO>,
+            0x14 | 0x15 => Self::addressing::<ZeroPageIndexed<true>, INST, IO>,
+            0x16 | 0x17 => match row {
+                0x8 | 0xA => Self::addressing::<ZeroPageIndexed<false>, INST, IO>,
+                _ => Self::addressing::<ZeroPageIndexed<true>, INST, IO>,
+            },
+            0x18 | 0x1A => Self::addressing::<Implied, INST, IO>,
+            0x19 | 0x1B => Self::addressing::<AbsoluteIndexed<false>, INST, IO>,
+            0x1C | 0x1D => Self::addressing::<AbsoluteIndexed<true>, INST, IO>,
+            0x1E | 0x1F => match row {
+                0x8 | 0xA => Self::addressing::<AbsoluteIndexed<false>, INST, IO>,
+                _ => Self::addressing::<AbsoluteIndexed<true>, INST, IO>,
+            },
+            _ => unreachable!("No addressing mode implemented for {:02X}", column),
+        }
+    }
+
+    fn queue_branch(&mut self) {
+        let should_branch = match self.opcode {
+            0x10 => !self.registers.p.contains(StatusFlags::N),
+            0x30 => self.registers.p.contains(StatusFlags::N),
+            0x50 => !self.registers.p.contains(StatusFlags::V),
+            0x70 => self.registers.p.contains(StatusFlags::V),
+            0x90 => !self.registers.p.contains(StatusFlags::C),
+            0xB0 => self.registers.p.contains(StatusFlags::C),
+            0xD0 => !self.registers.p.contains(StatusFlags::Z),
+            0xF0 => self.registers.p.contains(StatusFlags::Z),
+            0x80 => true,
+            _ => unreachable!("{:02X}", self.opcode),
+        };
+
+        if should_branch {
+            self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::pull_branch_operand));
+            self.queue_microcode(Self::pc, BusDirection::Read(Self::branch));
+        } else {
+            self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::pull_operand));
+        }
+
+        self.queue_decode();
+    }
+
+    fn queue_brk(&mut self) {
+        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::nop));
+        self.queue_microcode(
+            Self::stack_push,
+            BusDirection::Write(Self::write_instruction::<PCH>),
+        );
+        self.queue_microcode(
+            Self::stack_push,
+            BusDirection::Write(Self::write_instruction::<PCL>),
+        );
+        self.queue_microcode(
+            Self::stack_push,
+            BusDirection::Write(|cpu| {
+                cpu.write_instruction::<PHP>();
+                cpu.queue_vector();
+            }),
+        );
+    }
+
+    fn queue_interrupt(&mut self) {
+        self.queue_microcode(Self::pc, BusDirection::Read(Self::nop));
+        self.queue_microcode(
+            Self::stack_push,
+            BusDirection::Write(Self::write_instruction::<PCH>),
+        );
+        self.queue_microcode(
+            Self::stack_push,
+            BusDirection::Write(Self::write_instruction::<PCL>),
+        );
+        self.queue_microcode(
+            Self::stack_push,
+            BusDirection::Write(|cpu| {
+                cpu.write_instruction::<INT>();
+                cpu.queue_vector();
+            }),
+        );
+    }
+
+    fn queue_jmp(&mut self) {
+        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::pull_operand));
+        self.queue_read::<JMP>(Self::pc_inc);
+        self.queue_decode();
+    }
+
+    fn queue_indirect_jmp(&mut self) {
+        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::buffer_low));
+        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::buffer_high));
+        self.queue_microcode(Self::address, BusDirection::Read(Self::pull_operand));
+        self.queue_read::<JMP>(|cpu| cpu.address().index(1));
+        self.queue_decode();
+    }
+
+    fn queue_jsr(&mut self) {
+        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::pull_operand));
+        self.queue_microcode(Self::stack, BusDirection::Read(Self::nop));
+        self.queue_microcode(
+            Self::stack_push,
+            BusDirection::Write(Self::write_instruction::<PCH>),
+        );
+        self.queue_microcode(
+            Self::stack_push,
+            BusDirection::Write(Self::write_instruction::<PCL>),
+        );
+        self.queue_read::<JSR>(Self::pc_inc);
+        self.queue_decode();
+    }
+
+    fn queue_rti(&mut self) {
+        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::nop));
+        self.queue_microcode(Self::stack, BusDirection::Read(Self::nop));
+        self.queue_read::<PLP>(Self::stack_pull);
+        self.queue_read::<PCL>(Self::stack_pull);
+        self.queue_read::<PCH>(Self::stack_pull);
+        self.queue_decode();
+    }
+
+    fn queue_rts(&mut self) {
+        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::nop));
+        self.queue_microcode(Self::stack, BusDirection::Read(Self::nop));
+        self.queue_read::<PCL>(Self::stack_pull);
+        self.queue_read::<PCH>(Self::stack_pull);
+        self.queue_microcode(Self::pc_inc, BusDirection::Read(Self::nop));
+        self.queue_decode();
+    }
+}
+
+impl<V: Variant> MicrocodeInstructions for Cpu6502<V> {
+    fn pull_operand(&mut self) {
+        self.registers.operand = self.data_latch;
+    }
+
+    fn read_instruction<INST: ReadInstruction>(&mut self) {
+        INST::execute(&mut self.registers, &self.data_latch);
+    }
+
+    fn write_instruction<INST: WriteInstruction>(&mut self) {
+        INST::execute(&mut self.registers, &mut self.data_latch);
+    }
+
+    fn borrow_accumulator(&mut self) -> &mut u8 {
+        &mut self.registers.a
+    }
+
+    fn index_x(&self) -> u8 {
+        self.registers.x
+    }
+
+    fn index_y(&self) -> u8 {
+        self.registers.y
+    }
+
+    fn buffer_low(&mut self) {
+        self.registers.address_buffer.set_low(self.data_latch);
+    }
+
+    fn buffer_high(&mut self) {
+        self.registers.address_buffer.set_high(self.data_latch);
+    }
+}
+
+impl<V: Variant> Default for Cpu6502<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V: Variant> Cpu6502<V> {
+    pub fn new() -> Self {
+        let mut cpu = Self {
+            registers: Registers::default(),
+            timing: VecDeque::with_capacity(8),
+            decode_cache: [None; 256],
+            opcode: 0,
+            data_latch: 0,
+            cycles: 0,
+            nmi_line: false,
+            nmi_detected: false,
+            irq_line: false,
+            interrupt_pending: false,
+            skip_poll: false,
+            dma_halt: false,
+            halted_address: Address(0),
+            state: CpuState::Running,
+            variant: PhantomData,
+        };
+        cpu.power_on();
+        cpu
+    }
+
+    /// Drives the NMI input. NMI is edge triggered, so only the transition into the asserted state is latched.
+    pub fn set_nmi(&mut self, asserted: bool) {
+        if asserted && !self.nmi_line {
+            self.nmi_detected = true;
+        }
+        self.nmi_line = asserted;
+    }
+
+    /// Drives the IRQ input. IRQ is level triggered and masked by [`StatusFlags::I`].
+    pub fn set_irq(&mut self, asserted: bool) {
+        self.irq_line = asserted;
+    }
+
+    /// Drives RDY low. The CPU finishes any write cycles and then stops before its next read until released.
+    pub fn set_dma_halt(&mut self, asserted: bool) {
+        self.dma_halt = asserted;
+    }
+
+    fn poll_interrupts(&mut self) {
+        self.interrupt_pending =
+            self.nmi_detected || (self.irq_line && !self.registers.p.contains(StatusFlags::I));
+    }
+
+    /// A taken branch polls on its operand fetch rather than the cycle that follows, so an interrupt arriving
+    /// later is held off until after the next instruction unless a page crossing adds a final cycle that polls.
+    fn pull_branch_operand(&mut self) {
+        self.pull_operand();
+        self.poll_interrupts();
+        self.skip_poll = true;
+    }
+
+    /// Applies the branch offset in `operand`, adding a cycle when the target is on another page.
+    pub(crate) fn branch(&mut self) {
+        let mut pc = self.registers.pc;
+        pc.offset(self.registers.operand as i8);
+
+        if self.registers.pc.high() != pc.high() {
+            self.push_microcode(
+                |cpu| {
+                    let mut address = cpu.registers.pc;
+                    address.offset(cpu.registers.operand as i8);
+                    address.set_high(cpu.registers.pc.high());
+                    address
+                },
+                BusDirection::Read(|cpu| cpu.registers.pc.offset(cpu.registers.operand as i8)),
+            );
+        } else {
+            self.registers.pc = pc;
+        }
+    }
+
+    fn halted_address(&mut self) -> Address {
+        self.halted_address
+    }
+
+    fn fetch_opcode(&mut self) -> Address {
+        // A pending interrupt suppresses the PC increment of the opcode fetch
+        if self.interrupt_pending {
+            self.pc()
+        } else {
+            self.pc_inc()
+        }
+    }
+
+    fn queue_vector(&mut self) {
+        // An NMI detected before the vector fetch hijacks BRK and IRQ sequences
+        if self.nmi_detected {
+            self.nmi_detected = false;
+            self.queue_microcode(Self::vector::<0xFA>, BusDirection::Read(Self::vector_low));
+            self.queue_microcode(Self::vector::<0xFB>, BusDirection::Read(Self::vector_high));
+        } else {
+            self.queue_microcode(Self::vector::<0xFE>, BusDirection::Read(Self::vector_low));
+            self.queue_microcode(Self::vector::<0xFF>, BusDirection::Read(Self::vector_high));
+        }
+        self.queue_decode();
+    }
+
+    fn vector_low(&mut self) {
+        self.read_instruction::<PCL>();
+        self.registers.p.insert(StatusFlags::I);
+        if V::CMOS {
+            self.registers.p.remove(StatusFlags::D);
+        }
+    }
+
+    fn vector_high(&mut self) {
+        self.read_instruction::<PCH>();
+        // The first instruction of a handler always runs before another interrupt is serviced
+        self.interrupt_pending = false;
+    }
+
+    /// The NMOS opcode matrix shared by the 6502 and its derivatives. `DECIMAL` selects whether ADC and SBC honour
+    /// [`StatusFlags::D`].
+    pub fn decode_nmos<const DECIMAL: bool>(&mut self) -> fn(&mut Self) {
+        // 0000_0000
+        // bit 7-5: row
+        // bit 4-0: column
+        // bit 1-0: block
+        let row = (self.opcode & 0b1110_0000) >> 4;
+        let column = self.opcode & 0b0001_1111;
+        let block = self.opcode & 0b0000_0011;
+
+        if self.opcode & 0x1F == 0x10 {
+            Self::queue_branch
+        } else {
+            match (row, column, block) {
+                // Control
+                (0x0, 0x0, _) => Self::queue_brk,
+                (0x2, 0x0, _) => Self::queue_jsr,
+                (0x4, 0x0, _) => Self::queue_rti,
+                (0x6, 0x0, _) => Self::queue_rts,
+                (0x2, 0x4, _) => self.decode_addressing::<BIT, Read>(row, column),
+                (0x0, 0x8, _) => self.decode_addressing::<PHP, Write>(row, column),
+                (0x2, 0x8, _) => self.decode_addressing::<PLP, Read>(row, column),
+                (0x4, 0x8, _) => self.decode_addressing::<PHA, Write>(row, column),
+                (0x6, 0x8, _) => self.decode_addressing::<PLA, Read>(row, column),
+                (0x8, 0x8, _) => self.decode_addressing::<DEY, Read>(row, column),
+                (0xA, 0x8, _) => self.decode_addressing::<TAY, Read>(row, column),
+                (0xC, 0x8, _) => self.decode_addressing::<INY, Read>(row, column),
+                (0xE, 0x8, _) => self.decode_addressing::<INX, Read>(row, column),
+                (0x2, 0xC, _) => self.decode_addressing::<BIT, Read>(row, column),
+                (0x4, 0xC, _) => Self::queue_jmp,
+                (0x6, 0xC, _) => Self::queue_indirect_jmp,
+                (0x0, 0x18, _) => self.decode_addressing::<CLC, Read>(row, column),
+                (0x2, 0x18, _) => self.decode_addressing::<SEC, Read>(row, column),
+                (0x4, 0x18, _) => self.decode_addressing::<CLI, Read>(row, column),
+                (0x6, 0x18, _) => self.decode_addressing::<SEI, Read>(row, column),
+                (0x8, 0x18, _) => self.decode_addressing::<TYA, Read>(row, column),
+                (0xA, 0x18, _) => self.decode_addressing::<CLV, Read>(row, column),
+                (0xC, 0x18, _) => self.decode_addressing::<CLD, Read>(row, column),
+                (0xE, 0x18, _) => self.decode_addressing::<SED, Read>(row, column),
+
+                (0x8, 0x1C, _) => Self::addressing::<AbsoluteIndexedUnstable<true>, SHY, Write>,
+                (0x8, _, 0) => self.decode_addressing::<STY, Write>(row, column),
+                (0xA, _, 0) => self.decode_addressing::<LDY, Read>(row, column),
+                (_, 0x14, _) => self.decode_addressing::<NOP, Read>(row, column),
+                (_, 0x1C, _) => self.decode_addressing::<NOP, Read>(row, column),
+                (0xC, _, 0) => self.decode_addressing::<CPY, Read>(row, column),
+                (0xE, _, 0) => self.decode_addressing::<CPX, Read>(row, column),
+                (_, _, 0) => self.decode_addressing::<NOP, Read>(row, column),
+
+                // ALU
+                (0x0, _, 1) => self.decode_addressing::<ORA, Read>(row, column),
+                (0x2, _, 1) => self.decode_addressing::<AND, Read>(row, column),
+                (0x4, _, 1) => self.decode_addressing::<EOR, Read>(row, column),
+                (0x6, _, 1) => self.decode_addressing::<ADC<DECIMAL>, Read>(row, column),
+                (0x8, _, 1) => self.decode_addressing::<STA, Write>(row, column),
+                (0xA, _, 1) => self.decode_addressing::<LDA, Read>(row, column),
+                (0xC, _, 1) => self.decode_addressing::<CMP, Read>(row, column),
+                (0xE, _, 1) => self.decode_addressing::<SBC<DECIMAL>, Read>(row, column),
+
+                // RMW
+                (0x0..=0x6, 0x2, _) | (_, 0x12, _) => Self::queue_jam,
+                (0x0, _, 2) => self.decode_addressing::<ASL, ReadWrite>(row, column),
+                (0x2, _, 2) => self.decode_addressing::<ROL, ReadWrite>(row, column),
+                (0x4, _, 2) => self.decode_addressing::<LSR, ReadWrite>(row, column),
+                (0x6, _, 2) => self.decode_addressing::<ROR, ReadWrite>(row, column),
+                (0x8, 0xA, _) => self.decode_addressing::<TXA, Read>(row, column),
+                (0x8, 0x1A, _) => self.decode_addressing::<TXS, Read>(row, column),
+                (0x8, 0x1E, _) => Self::addressing::<AbsoluteIndexedUnstable<false>, SHX, Write>,
+                (0x8, _, 2) => self.decode_addressing::<STX, Write>(row, column),
+                (0xA, 0xA, _) => self.decode_addressing::<TAX, Read>(row, column),
+                (0xA, 0x1A, _) => self.decode_addressing::<TSX, Read>(row, column),
+                (0xA, _, 2) => self.decode_addressing::<LDX, Read>(row, column),
+                (0xC, 0xA, _) => self.decode_addressing::<DEX, Read>(row, column),
+                (0xC, _, 2) => self.decode_addressing::<DEC, ReadWrite>(row, column),
+                (0xE, 0xA, _) => self.decode_addressing::<NOP, Read>(row, column),
+                (0xE, _, 2) => self.decode_addressing::<INC, ReadWrite>(row, column),
+
+                // Illegal
+                (0x0 | 0x2, 0xB, _) => self.decode_addressing::<ANC, Read>(row, column),
+                (0x4, 0xB, _) => self.decode_addressing::<ALR, Read>(row, column),
+                (0x6, 0xB, _) => self.decode_addressing::<ARR<DECIMAL>, Read>(row, column),
+                (0x8, 0xB, _) => self.decode_addressing::<XAA, Read>(row, column),
+                (0x8, 0x13, _) => Self::addressing::<IndirectIndexedYUnstable, SHA, Write>,
+                (0x8, 0x1B, _) => Self::addressing::<AbsoluteIndexedUnstable<false>, TAS, Write>,
+                (0x8, 0x1F, _) => Self::addressing::<AbsoluteIndexedUnstable<false>, SHA, Write>,
+                (0xA, 0x1B, _) => Self::addressing::<AbsoluteIndexed<false>, LAS, Read>,
+                (0xC, 0xB, _) => self.decode_addressing::<SBX, Read>(row, column),
+                (0x0, _, 3) => self.decode_addressing::<SLO, ReadWrite>(row, column),
+                (0x2, _, 3) => self.decode_addressing::<RLA, ReadWrite>(row, column),
+                (0x4, _, 3) => self.decode_addressing::<SRE, ReadWrite>(row, column),
+                (0x6, _, 3) => self.decode_addressing::<RRA<DECIMAL>, ReadWrite>(row, column),
+                (0x8, _, 3) => self.decode_addressing::<SAX, Write>(row, column),
+                (0xA, _, 3) => self.decode_addressing::<LAX, Read>(row, column),
+                (0xC, _, 3) => self.decode_addressing::<DCP, ReadWrite>(row, column),
+                (0xE, 0xB, _) => self.decode_addressing::<SBC<DECIMAL>, Read>(row, column),
+                (0xE, _, 3) => self.decode_addressing::<ISC<DECIMAL>, ReadWrite>(row, column),
+                _ => Self::queue_jam,
+            }
+        }
+    }
+
+    // JAM locks up the CPU until it is reset
+    pub(crate) fn queue_jam(&mut self) {
+        self.queue_microcode(
+            Self::pc,
+            BusDirection::Read(|cpu| cpu.state = CpuState::Halted(cpu.opcode)),
+        );
+    }
+
+    /// Queues a cycle between the queued instruction and its trailing opcode fetch.
+    pub(crate) fn queue_before_decode(
+        &mut self,
+        address_mode: fn(&mut Self) -> Address,
+        bus_mode: BusDirection<Self>,
+    ) {
+        let decode = self.timing.pop_back();
+        self.queue_microcode(address_mode, bus_mode);
+        self.timing.extend(decode);
+    }
+
+    pub(crate) fn addressing<
+        ADDRESSING: AddressingMode<Self, INST, IO>,
+        INST: Instruction<IO>,
+        IO: IOMode,
+    >(
+        &mut self,
+    ) {
+        ADDRESSING::enqueue(self);
+    }
+
+    pub fn power_on(&mut self) {
+        self.registers = Registers::default();
+        self.nmi_line = false;
+        self.irq_line = false;
+        self.reset();
+    }
+
+    /// The reset sequence is an interrupt with the stack writes suppressed, so S still drops by three.
+    pub fn reset(&mut self) {
+        self.registers.p.insert(StatusFlags::I);
+        if V::CMOS {
+            self.registers.p.remove(StatusFlags::D);
+        }
+        self.nmi_detected = false;
+        self.interrupt_pending = false;
+        self.skip_poll = false;
+        self.state = CpuState::Running;
+        self.clear_microcode();
+        self.queue_read::<NOP>(Self::pc_inc);
+        self.queue_read::<NOP>(Self::pc_inc);
+        self.queue_read::<NOP>(Self::stack_push);
+        self.queue_read::<NOP>(Self::stack_push);
+        self.queue_read::<NOP>(Self::stack_push);
+        self.queue_microcode(Self::vector::<0xFC>, BusDirection::Read(Self::vector_low));
+        self.queue_microcode(Self::vector::<0xFD>, BusDirection::Read(Self::vector_high));
+        self.queue_decode();
+    }
+}
+
+impl<V: Variant> Cpu for Cpu6502<V> {
+    fn cycle(&mut self, bus: &mut impl Bus) {
+        self.cycles = self.cycles.wrapping_add(1);
+        match self.state {
+            CpuState::Running => {}
+            CpuState::WaitingForInterrupt => {
+                // Any interrupt resumes execution, a masked IRQ simply continues after WAI
+                if !self.nmi_detected && !self.irq_line {
+                    return;
+                }
+                self.state = CpuState::Running;
+                self.poll_interrupts();
+            }
+            CpuState::WaitingForDma if self.dma_halt => {
+                bus.halted_read(self.halted_address);
+                return;
+            }
+            CpuState::WaitingForDma => self.state = CpuState::Running,
+            CpuState::Halted(_) => return,
+        }
+
+        // RDY stops the CPU with its read already on the bus, so the address is fixed and the read repeats every
+        // halted cycle
+        if self.dma_halt && matches!(self.timing.front(), Some((_, BusDirection::Read(_)))) {
+            if let Some((address_mode, direction)) = self.timing.pop_front() {
+                self.halted_address = address_mode(self);
+                self.timing.push_front((Self::halted_address, direction));
+            }
+            self.state = CpuState::WaitingForDma;
+            bus.halted_read(self.halted_address);
+            return;
+        }
+
+        let Some(microcode) = self.timing.pop_front() else {
+            self.state = CpuState::Halted(self.opcode);
+            return;
+        };
+
+        // Only the next opcode fetch remains queued during the final cycle of an instruction. Interrupts are
+        // polled before the cycle executes so the line state from the previous cycle and the I flag from
+        // before CLI/SEI/PLP take effect are what count.
+        if self.timing.len() == 1 && !core::mem::take(&mut self.skip_poll) {
+            self.poll_interrupts();
+        }
+
+        match microcode {
+            (address_mode, BusDirection::Read(operation)) => {
+                self.data_latch = bus.read(address_mode(self));
+                operation(self);
+            }
+            (address_mode, BusDirection::Write(operation)) => {
+                let address = address_mode(self);
+                operation(self);
+                bus.write(address, self.data_latch);
+            }
+        }
+    }
+
+    fn state(&self) -> CpuState {
+        self.state
+    }
+
+    fn power_on(&mut self) {
+        Cpu6502::power_on(self);
+    }
+
+    fn reset(&mut self) {
+        Cpu6502::reset(self);
+    }
+
+    fn set_nmi(&mut self, asserted: bool) {
+        Cpu6502::set_nmi(self, asserted);
+    }
+
+    fn set_irq(&mut self, asserted: bool) {
+        Cpu6502::set_irq(self, asserted);
+    }
+
+    fn set_dma_halt(&mut self, asserted: bool) {
+        Cpu6502::set_dma_halt(self, asserted);
+    }
+}
@@ -133,6 +133,13 @@ impl ReadInstruction for SEC {
     }
 }
 
+pub struct CLI;
+impl ReadInstruction for CLI {
+    fn execute(registers: &mut Registers, _: &u8) {
+        registers.p.set(StatusFlags::I, false);
+    }
+}
+
 pub struct SEI;
 impl ReadInstruction for SEI {
     fn execute(registers: &mut Registers, _: &u8) {
@@ -230,8 +237,13 @@ impl ReadInstruction for EOR {
 }
 
 pub struct ADC<const ALLOW_DECIMAL: bool>;
-impl ReadInstruction for ADC<false> {
+impl<const ALLOW_DECIMAL: bool> ReadInstruction for ADC<ALLOW_DECIMAL> {
     fn execute(registers: &mut Registers, data: &u8) {
+        if ALLOW_DECIMAL && registers.p.contains(StatusFlags::D) {
+            Self::execute_decimal(registers, data);
+            return;
+        }
+
         let (result, add_overflow) = registers.a.overflowing_add(*data);
         let (result, carry_overflow) = result.overflowing_add(registers.p.bits() & 1);
         registers
@@ -246,6 +258,35 @@ impl ReadInstruction for ADC<false> {
     }
 }
 
+impl<const ALLOW_DECIMAL: bool> ADC<ALLOW_DECIMAL> {
+    // NMOS decimal addition, following Bruce Clark's "Decimal Mode" appendix A
+    fn execute_decimal(registers: &mut Registers, data: &u8) {
+        let carry = registers.p.bits() & 1;
+        let mut low = (registers.a & 0x0F) as u16 + (*data & 0x0F) as u16 + carry as u16;
+        if low >= 0x0A {
+            low = ((low + 0x06) & 0x0F) + 0x10;
+        }
+
+        // N and V are taken from the sum before the high digit is adjusted
+        let signed = (registers.a & 0xF0) as i8 as i16 + (*data & 0xF0) as i8 as i16 + low as i16;
+        registers.p.set(StatusFlags::N, signed & 0x80 > 0);
+        registers
+            .p
+            .set(StatusFlags::V, !(-128..=127).contains(&signed));
+
+        // Z reflects the binary sum
+        let binary = registers.a.wrapping_add(*data).wrapping_add(carry);
+        registers.p.set(StatusFlags::Z, binary == 0);
+
+        let mut sum = (registers.a & 0xF0) as u16 + (*data & 0xF0) as u16 + low;
+        if sum >= 0xA0 {
+            sum += 0x60;
+        }
+        registers.p.set(StatusFlags::C, sum >= 0x100);
+        registers.a = sum as u8;
+    }
+}
+
 pub struct STA;
 impl WriteInstruction for STA {
     fn execute(registers: &mut Registers, data: &mut u8) {
@@ -272,11 +313,13 @@ impl ReadInstruction for CMP {
     }
 }
 
-pub struct SBC;
-impl ReadInstruction for SBC {
+pub struct SBC<const ALLOW_DECIMAL: bool>;
+impl<const ALLOW_DECIMAL: bool> ReadInstruction for SBC<ALLOW_DECIMAL> {
     fn execute(registers: &mut Registers, data: &u8) {
+        let a = registers.a;
+        let carry = registers.p.bits() & 1;
         let (result, add_overflow) = registers.a.overflowing_add(!*data);
-        let (result, carry_overflow) = result.overflowing_add(registers.p.bits() & 1);
+        let (result, carry_overflow) = result.overflowing_add(carry);
         registers
             .p
             .set(StatusFlags::C, add_overflow | carry_overflow);
@@ -286,6 +329,20 @@ impl ReadInstruction for SBC {
         );
         registers.a = result;
         registers.p.set_value_flags(registers.a);
+
+        // NMOS decimal subtraction only corrects the accumulator, flags match the binary result
+        if ALLOW_DECIMAL && registers.p.contains(StatusFlags::D) {
+            let mut low = (a & 0x0F) as i16 - (*data & 0x0F) as i16 + carry as i16 - 1;
+            if low < 0 {
+                low = ((low - 0x06) & 0x0F) - 0x10;
+            }
+
+            let mut difference = (a & 0xF0) as i16 - (*data & 0xF0) as i16 + low;
+            if difference < 0 {
+                difference -= 0x60;
+            }
+            registers.a = difference as u8;
+        }
     }
 }
 
@@ -437,11 +494,11 @@ impl ReadWriteInstruction for SRE {
     }
 }
 
-pub struct RRA;
-impl ReadWriteInstruction for RRA {
+pub struct RRA<const ALLOW_DECIMAL: bool>;
+impl<const ALLOW_DECIMAL: bool> ReadWriteInstruction for RRA<ALLOW_DECIMAL> {
     fn execute(registers: &mut Registers, data: &mut u8) {
         ROR::execute(registers, data);
-        ADC::execute(registers, data);
+        ADC::<ALLOW_DECIMAL>::execute(registers, data);
     }
 }
 
@@ -468,11 +525,132 @@ impl ReadWriteInstruction for DCP {
     }
 }
 
-pub struct ISC;
-impl ReadWriteInstruction for ISC {
+pub struct ISC<const ALLOW_DECIMAL: bool>;
+impl<const ALLOW_DECIMAL: bool> ReadWriteInstruction for ISC<ALLOW_DECIMAL> {
     fn execute(registers: &mut Registers, data: &mut u8) {
         INC::execute(registers, data);
-        SBC::execute(registers, data);
+        SBC::<ALLOW_DECIMAL>::execute(registers, data);
+    }
+}
+
+pub struct ANC;
+impl ReadInstruction for ANC {
+    fn execute(registers: &mut Registers, data: &u8) {
+        AND::execute(registers, data);
+        registers
+            .p
+            .set(StatusFlags::C, registers.p.contains(StatusFlags::N));
+    }
+}
+
+pub struct ALR;
+impl ReadInstruction for ALR {
+    fn execute(registers: &mut Registers, data: &u8) {
+        let mut value = registers.a & data;
+        LSR::execute(registers, &mut value);
+        registers.a = value;
+    }
+}
+
+pub struct ARR<const ALLOW_DECIMAL: bool>;
+impl<const ALLOW_DECIMAL: bool> ReadInstruction for ARR<ALLOW_DECIMAL> {
+    fn execute(registers: &mut Registers, data: &u8) {
+        let value = registers.a & data;
+        let carry = registers.p.contains(StatusFlags::C);
+        registers.a = (value >> 1) | if carry { 0b1000_0000 } else { 0 };
+        registers.p.set_value_flags(registers.a);
+
+        if ALLOW_DECIMAL && registers.p.contains(StatusFlags::D) {
+            // The BCD fixup is applied to the rotated value, but decided by the digits before rotation
+            registers
+                .p
+                .set(StatusFlags::V, (value ^ registers.a) & 0b0100_0000 != 0);
+            if (value & 0x0F) + (value & 0x01) > 0x05 {
+                registers.a = (registers.a & 0xF0) | (registers.a.wrapping_add(0x06) & 0x0F);
+            }
+            let high_carry = (value as u16 & 0xF0) + (value as u16 & 0x10) > 0x50;
+            if high_carry {
+                registers.a = registers.a.wrapping_add(0x60);
+            }
+            registers.p.set(StatusFlags::C, high_carry);
+        } else {
+            let bit6 = registers.a & 0b0100_0000 != 0;
+            let bit5 = registers.a & 0b0010_0000 != 0;
+            registers.p.set(StatusFlags::C, bit6);
+            registers.p.set(StatusFlags::V, bit6 ^ bit5);
+        }
+    }
+}
+
+pub struct SBX;
+impl ReadInstruction for SBX {
+    fn execute(registers: &mut Registers, data: &u8) {
+        let value = registers.a & registers.x;
+        registers.p.set(StatusFlags::C, value >= *data);
+        registers.x = value.wrapping_sub(*data);
+        registers.p.set_value_flags(registers.x);
+    }
+}
+
+// Unstable on real hardware, uses the commonly observed $EE for the bits pulled up on the internal bus
+pub struct XAA;
+impl ReadInstruction for XAA {
+    fn execute(registers: &mut Registers, data: &u8) {
+        registers.a = (registers.a | 0xEE) & registers.x & data;
+        registers.p.set_value_flags(registers.a);
+    }
+}
+
+pub struct LAS;
+impl ReadInstruction for LAS {
+    fn execute(registers: &mut Registers, data: &u8) {
+        let value = registers.stack & data;
+        registers.a = value;
+        registers.x = value;
+        registers.stack = value;
+        registers.p.set_value_flags(value);
+    }
+}
+
+// SHA, SHX, SHY and TAS store `value & (H + 1)` where H is the high byte of the unindexed address. When indexing
+// crosses a page the stored value also replaces the high byte of the target, so these resolve the address themselves.
+fn store_high_and(registers: &mut Registers, data: &mut u8, value: u8, index: u8) {
+    let base = registers.address_buffer;
+    let value = value & base.high().wrapping_add(1);
+    let mut target = base + index;
+    if target.high() != base.high() {
+        target.set_high(value);
+    }
+    registers.address_buffer = target;
+    *data = value;
+}
+
+pub struct SHA;
+impl WriteInstruction for SHA {
+    fn execute(registers: &mut Registers, data: &mut u8) {
+        store_high_and(registers, data, registers.a & registers.x, registers.y);
+    }
+}
+
+pub struct SHX;
+impl WriteInstruction for SHX {
+    fn execute(registers: &mut Registers, data: &mut u8) {
+        store_high_and(registers, data, registers.x, registers.y);
+    }
+}
+
+pub struct SHY;
+impl WriteInstruction for SHY {
+    fn execute(registers: &mut Registers, data: &mut u8) {
+        store_high_and(registers, data, registers.y, registers.x);
+    }
+}
+
+pub struct TAS;
+impl WriteInstruction for TAS {
+    fn execute(registers: &mut Registers, data: &mut u8) {
+        registers.stack = registers.a & registers.x;
+        store_high_and(registers, data, registers.stack, registers.y);
     }
 }
 
@@ -503,6 +681,14 @@ impl WriteInstruction for PCH {
     }
 }
 
+// Status push for hardware interrupts, which unlike BRK and PHP leaves B clear
+pub struct INT;
+impl WriteInstruction for INT {
+    fn execute(registers: &mut Registers, data: &mut u8) {
+        *data = ((registers.p | StatusFlags::Reserved) - StatusFlags::B).bits();
+    }
+}
+
 pub trait MicrocodeControl
 where
     Self: Sized,
@@ -523,6 +709,7 @@ where
         address_mode: fn(&mut Self) -> Address,
     );
     fn queue_write<INST: WriteInstruction>(&mut self, address_mode: fn(&mut Self) -> Address);
+    fn queue_modify(&mut self, address_mode: fn(&mut Self) -> Address);
     fn queue_decode(&mut self);
     fn clear_microcode(&mut self);
 }
@@ -177,6 +177,56 @@ byte_units!(usize);
 pub trait Bus {
     fn read(&mut self, address: Address) -> u8;
     fn write(&mut self, address: Address, data: u8);
+    fn power_on(&mut self, _ram: RamInit) {}
+    fn reset(&mut self) {}
+
+    /// A read the CPU repeats while held by RDY, which is lost on cycles a DMA unit drives the bus.
+    fn halted_read(&mut self, address: Address) {
+        self.read(address);
+    }
+
+    /// Advances the devices on the bus by one master clock cycle, returning whether the CPU is clocked on this cycle.
+    fn clock_pulse(&mut self) -> bool {
+        true
+    }
+
+    fn signals(&self) -> Signals {
+        Signals::default()
+    }
+}
+
+/// Interrupt lines driven into the CPU by the devices on a bus.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Signals {
+    pub nmi: bool,
+    pub irq: bool,
+    /// RDY pulled low by a DMA unit, which stops the CPU on its next read cycle.
+    pub dma_halt: bool,
+}
+
+/// Contents of RAM at power on. Real hardware powers up with mostly random RAM, seeded here so runs are repeatable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RamInit {
+    Zeroed,
+    Random(u64),
+}
+
+impl RamInit {
+    pub fn fill(self, memory: &mut [u8]) {
+        match self {
+            RamInit::Zeroed => memory.fill(0),
+            RamInit::Random(seed) => {
+                // xorshift64, which must not be seeded with zero
+                let mut state = seed.max(1);
+                for byte in memory {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    *byte = state as u8;
+                }
+            }
+        }
+    }
 }
 
 pub struct System<CPU: Cpu, BUS: Bus> {
@@ -190,8 +240,35 @@ impl<CPU: Cpu + Send + 'static, BUS: Bus + Send + 'static> System<CPU, BUS> {
     }
 
     pub fn clock_pulse(&mut self) {
-        let cpu = &mut self.cpu;
-        cpu.cycle(&mut self.bus);
+        if self.bus.clock_pulse() {
+            let signals = self.bus.signals();
+            self.cpu.set_nmi(signals.nmi);
+            self.cpu.set_irq(signals.irq);
+            self.cpu.set_dma_halt(signals.dma_halt);
+            self.cpu.cycle(&mut self.bus);
+        }
+    }
+
+    pub fn bus(&self) -> &BUS {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut BUS {
+        &mut self.bus
+    }
+
+    pub fn cpu_state(&self) -> CpuState {
+        self.cpu.state()
+    }
+
+    pub fn power_on(&mut self, ram: RamInit) {
+        self.bus.power_on(ram);
+        self.cpu.power_on();
+    }
+
+    pub fn soft_reset(&mut self) {
+        self.bus.reset();
+        self.cpu.reset();
     }
 
     pub fn run(mut self, clock_signal: Receiver<u64>) {
@@ -280,10 +357,24 @@ mod tests {
     use strum::ParseError;
 
     use crate::famicom::{
+        apu::{Apu, Pulse, PulseChannel, NTSC_RATES},
         mapper::{mapper_from, NromChrMapper, NromPrgMapper},
-        rom::{ntsc_system, RomImage},
+        mixer::{Mixer, Panning},
+        ntsc::NtscFilter,
+        palette::{Palette, PaletteError},
+        ppu::Ppu,
+        rom::{
+            ntsc_system, system_for, ConsoleType, ExtendedConsoleType, NametableLayout, RomError,
+            RomImage, VsHardwareType, VsPpuType,
+        },
         *,
     };
+    use crate::isa6502::{
+        cmos::{self, Wdc65C02},
+        cpu::{Cpu6502, Nmos, Variant},
+        instructions::{ReadInstruction, ADC, ANC, ARR, SBC, SBX},
+        CpuState, Registers, StatusFlags,
+    };
 
     use super::*;
 
@@ -356,8 +447,8 @@ mod tests {
 
         let test_rom = &RomImage::load(File::open(path).unwrap()).unwrap();
         let mut system = ntsc_system(
-            NromPrgMapper::new_with_ram(test_rom),
-            NromChrMapper::new(test_rom),
+            NromPrgMapper::new_with_ram(test_rom).unwrap(),
+            NromChrMapper::new(test_rom).unwrap(),
         );
 
         // Test rom initialization
@@ -372,11 +463,23 @@ mod tests {
             if maybe_debug == [0xDE, 0xB0, 0x61] {
                 break;
             }
+            assert_running(&system);
         }
 
-        // Cycle until status flag changes from 0x80 (running)
-        while system.bus.read(Address(0x6000)) == 0x80 {
+        // Cycle until status flag changes from 0x80 (running), pressing reset whenever it asks with 0x81
+        loop {
+            match system.bus.read(Address(0x6000)) {
+                0x80 => {}
+                0x81 => {
+                    for _ in 0..Region::Ntsc.master_clock_rate() / 10 {
+                        system.clock_pulse();
+                    }
+                    system.soft_reset();
+                }
+                _ => break,
+            }
             system.clock_pulse();
+            assert_running(&system);
         }
 
         for _ in 0..10000000 {
@@ -411,8 +514,14 @@ mod tests {
             "Test status returned failure {:02x}: {}",
             test_status, error
         );
+    }
 
-        panic!("FK");
+    fn assert_running<CPU: Cpu + Send + 'static, BUS: Bus + Send + 'static>(
+        system: &System<CPU, BUS>,
+    ) {
+        if let CpuState::Halted(opcode) = system.cpu_state() {
+            panic!("CPU halted on opcode {:02X}", opcode);
+        }
     }
 
     #[test]
@@ -424,10 +533,54 @@ mod tests {
         blargg_test("nes-test-roms/instr_test-v5/rom_singles/02-implied.nes");
     }
 
+    #[test]
+    #[ignore = "needs the nes-test-roms submodule"]
+    fn instruction_immediate() {
+        blargg_test("nes-test-roms/instr_test-v5/rom_singles/03-immediate.nes");
+    }
+
+    #[test]
+    #[ignore = "needs the nes-test-roms submodule"]
+    fn instruction_abs_xy() {
+        blargg_test("nes-test-roms/instr_test-v5/rom_singles/07-abs_xy.nes");
+    }
+
+    #[test]
+    #[ignore = "needs the nes-test-roms submodule"]
+    fn apu_test() {
+        for rom in [
+            "1-len_ctr",
+            "2-len_table",
+            "3-irq_flag",
+            "4-jitter",
+            "5-len_timing",
+            "6-irq_flag_timing",
+            "7-dmc_basics",
+            "8-dmc_rates",
+        ] {
+            blargg_test(format!("nes-test-roms/apu_test/rom_singles/{rom}.nes"));
+        }
+    }
+
+    #[test]
+    #[ignore = "needs the nes-test-roms submodule"]
+    fn apu_reset() {
+        for rom in [
+            "4015_cleared",
+            "4017_timing",
+            "4017_written",
+            "irq_flag_cleared",
+            "len_ctrs_enabled",
+            "works_immediately",
+        ] {
+            blargg_test(format!("nes-test-roms/apu_reset/{rom}.nes"));
+        }
+    }
+
     #[test]
     fn nes_test() {
         let nestest = &load_nestest();
-        let (prg_mapper, chr_mapper) = mapper_from(nestest);
+        let (prg_mapper, chr_mapper) = mapper_from(nestest).unwrap();
 
         let mut system = ntsc_system(prg_mapper, chr_mapper);
 
@@ -486,6 +639,1460 @@ mod tests {
         nestest
     }
 
+    struct TestBus {
+        memory: Vec<u8>,
+        signals: Signals,
+    }
+
+    impl Bus for TestBus {
+        fn read(&mut self, address: Address) -> u8 {
+            self.memory[address]
+        }
+
+        fn write(&mut self, address: Address, data: u8) {
+            self.memory[address] = data;
+        }
+
+        fn signals(&self) -> Signals {
+            self.signals
+        }
+    }
+
+    // Reset enters at $8000, IRQ/BRK at $9000 and NMI at $A000
+    fn test_system<V: Variant + Send + 'static>(
+        program: &[(u16, &[u8])],
+    ) -> System<Cpu6502<V>, TestBus> {
+        let mut memory = vec![0u8; 0x10000];
+        memory[0xFFFA..].copy_from_slice(&[0x00, 0xA0, 0x00, 0x80, 0x00, 0x90]);
+        for (origin, code) in program {
+            let origin = *origin as usize;
+            memory[origin..origin + code.len()].copy_from_slice(code);
+        }
+
+        System::new(
+            Cpu6502::new(),
+            TestBus {
+                memory,
+                signals: Signals::default(),
+            },
+        )
+    }
+
+    fn run_cpu_cycles<CPU: Cpu + Send + 'static, BUS: Bus + Send + 'static>(
+        system: &mut System<CPU, BUS>,
+        cycles: u64,
+    ) {
+        for _ in 0..cycles {
+            system.clock_pulse();
+        }
+    }
+
+    #[test]
+    fn nmi_is_edge_triggered() {
+        // JMP $8000; handler: INX, RTI
+        let mut system =
+            test_system::<Ricoh2A03>(&[(0x8000, &[0x4C, 0x00, 0x80]), (0xA000, &[0xE8, 0x40])]);
+        run_cpu_cycles(&mut system, 20);
+
+        system.bus.signals.nmi = true;
+        run_cpu_cycles(&mut system, 100);
+        assert_eq!(system.log().x, 1, "Held NMI must only be serviced once");
+
+        system.bus.signals.nmi = false;
+        run_cpu_cycles(&mut system, 10);
+        system.bus.signals.nmi = true;
+        run_cpu_cycles(&mut system, 100);
+        assert_eq!(system.log().x, 2);
+    }
+
+    #[test]
+    fn irq_is_masked_by_interrupt_flag() {
+        // LDY #0; loop: INY, BNE loop; CLI; JMP $8006; handler: INX, JMP $9001
+        let mut system = test_system::<Ricoh2A03>(&[
+            (
+                0x8000,
+                &[0xA0, 0x00, 0xC8, 0xD0, 0xFD, 0x58, 0x4C, 0x06, 0x80],
+            ),
+            (0x9000, &[0xE8, 0x4C, 0x01, 0x90]),
+        ]);
+        system.bus.signals.irq = true;
+        run_cpu_cycles(&mut system, 1000);
+        assert_eq!(system.log().x, 0, "IRQ must be masked while I is set");
+
+        run_cpu_cycles(&mut system, 1000);
+        let log = system.log();
+        assert_eq!(log.x, 1);
+        assert_eq!(log.p & StatusFlags::I.bits(), StatusFlags::I.bits());
+        // CLI takes effect after the following instruction, then status is pushed with B and I clear
+        assert_eq!(system.bus.memory[0x01FD], 0x80);
+        assert_eq!(system.bus.memory[0x01FC], 0x06);
+        assert_eq!(system.bus.memory[0x01FB] & 0b0011_0100, 0b0010_0000);
+    }
+
+    #[test]
+    fn taken_branch_delays_irq_without_page_cross() {
+        // CLI; CLC; JMP branch; handler: JMP $9000
+        fn irq_return_address(branch: u16, code: &[u8], raised_after: u64) -> u16 {
+            let [low, high] = branch.to_le_bytes();
+            let mut system = test_system::<Nmos>(&[
+                (0x8000, &[0x58, 0x18, 0x4C, low, high]),
+                (branch, code),
+                (0x9000, &[0x4C, 0x00, 0x90]),
+            ]);
+            run_cpu_cycles(&mut system, raised_after);
+            system.bus.signals.irq = true;
+            run_cpu_cycles(&mut system, 100);
+            u16::from_le_bytes([system.bus.memory[0x01FC], system.bus.memory[0x01FD]])
+        }
+
+        // Reset takes 7 cycles, then CLI, CLC and JMP put the branch on cycles 15-17
+        // BCC +0; INX; JMP $8013
+        let same_page = [0x90, 0x00, 0xE8, 0x4C, 0x13, 0x80];
+        assert_eq!(irq_return_address(0x8010, &same_page, 14), 0x8012);
+        assert_eq!(
+            irq_return_address(0x8010, &same_page, 16),
+            0x8013,
+            "IRQ raised after the operand fetch waits for the following instruction"
+        );
+
+        // BCC +2 to $8100; INX; JMP $8101, with the page fixup on cycle 18 polling again
+        let mut page_cross = [0u8; 8];
+        page_cross[..2].copy_from_slice(&[0x90, 0x02]);
+        page_cross[4..].copy_from_slice(&[0xE8, 0x4C, 0x01, 0x81]);
+        assert_eq!(irq_return_address(0x80FC, &page_cross, 16), 0x8100);
+    }
+
+    #[test]
+    fn brk_pushes_break_flag() {
+        // BRK #$FF; handler: JMP $9000
+        let mut system =
+            test_system::<Ricoh2A03>(&[(0x8000, &[0x00, 0xFF]), (0x9000, &[0x4C, 0x00, 0x90])]);
+        run_cpu_cycles(&mut system, 7 + 7);
+        assert_eq!(system.log().pc, Address(0x9000));
+        assert_eq!(system.bus.memory[0x01FD], 0x80);
+        assert_eq!(system.bus.memory[0x01FC], 0x02);
+        assert_eq!(system.bus.memory[0x01FB] & 0b0011_0000, 0b0011_0000);
+    }
+
+    #[test]
+    fn nmi_hijacks_brk() {
+        // BRK #$FF; IRQ handler: JMP $9000; NMI handler: JMP $A000
+        let mut system = test_system::<Ricoh2A03>(&[
+            (0x8000, &[0x00, 0xFF]),
+            (0x9000, &[0x4C, 0x00, 0x90]),
+            (0xA000, &[0x4C, 0x00, 0xA0]),
+        ]);
+        // Reset sequence plus the opcode fetch and signature byte of BRK
+        run_cpu_cycles(&mut system, 7 + 2);
+        system.bus.signals.nmi = true;
+        run_cpu_cycles(&mut system, 5);
+        assert_eq!(system.log().pc, Address(0xA000));
+        assert_eq!(system.bus.memory[0x01FB] & 0b0011_0000, 0b0011_0000);
+
+        run_cpu_cycles(&mut system, 20);
+        assert_eq!(
+            system.log().pc.high(),
+            0xA0,
+            "Hijacked NMI must not be serviced twice"
+        );
+    }
+
+    fn from_bcd(value: u8) -> u8 {
+        (value >> 4) * 10 + (value & 0x0F)
+    }
+
+    fn to_bcd(value: u8) -> u8 {
+        ((value / 10) << 4) | (value % 10)
+    }
+
+    #[test]
+    fn decimal_arithmetic_matches_bcd() {
+        for a in (0..100).map(to_bcd) {
+            for operand in (0..100).map(to_bcd) {
+                for carry in [false, true] {
+                    let mut registers = Registers::default();
+                    registers.p.insert(StatusFlags::D);
+                    registers.p.set(StatusFlags::C, carry);
+                    registers.a = a;
+                    ADC::<true>::execute(&mut registers, &operand);
+                    let sum = from_bcd(a) + from_bcd(operand) + carry as u8;
+                    assert_eq!(registers.a, to_bcd(sum % 100), "{a:02X}+{operand:02X}");
+                    assert_eq!(registers.p.contains(StatusFlags::C), sum >= 100);
+
+                    let mut registers = Registers::default();
+                    registers.p.insert(StatusFlags::D);
+                    registers.p.set(StatusFlags::C, carry);
+                    registers.a = a;
+                    SBC::<true>::execute(&mut registers, &operand);
+                    let difference = from_bcd(a) as i16 - from_bcd(operand) as i16 - !carry as i16;
+                    assert_eq!(
+                        registers.a,
+                        to_bcd(difference.rem_euclid(100) as u8),
+                        "{a:02X}-{operand:02X}"
+                    );
+                    assert_eq!(registers.p.contains(StatusFlags::C), difference >= 0);
+                }
+            }
+        }
+    }
+
+    // Bruce Clark's NMOS reference sequences from "Decimal Mode" appendix B, giving A, C, N, V and Z
+    fn clark_adc(a: u8, b: u8, carry: bool) -> (u8, bool, bool, bool, bool) {
+        // Sequence 1 for A and C
+        let mut al = (a & 0x0F) as i16 + (b & 0x0F) as i16 + carry as i16;
+        if al >= 0x0A {
+            al = ((al + 0x06) & 0x0F) + 0x10;
+        }
+        let mut sum = (a & 0xF0) as i16 + (b & 0xF0) as i16 + al;
+        if sum >= 0xA0 {
+            sum += 0x60;
+        }
+
+        // Sequence 2 for N and V, the same sum with signed high digits
+        let signed = (a & 0xF0) as i8 as i16 + (b & 0xF0) as i8 as i16 + al;
+
+        // Z comes from the binary sum
+        let binary = (a as u16 + b as u16 + carry as u16) as u8;
+        (
+            sum as u8,
+            sum >= 0x100,
+            signed & 0x80 != 0,
+            !(-128..=127).contains(&signed),
+            binary == 0,
+        )
+    }
+
+    fn clark_sbc(a: u8, b: u8, carry: bool) -> (u8, bool, bool, bool, bool) {
+        // Sequence 3 for A, with every flag taken from the binary difference
+        let mut al = (a & 0x0F) as i16 - (b & 0x0F) as i16 + carry as i16 - 1;
+        if al < 0 {
+            al = ((al - 0x06) & 0x0F) - 0x10;
+        }
+        let mut difference = (a & 0xF0) as i16 - (b & 0xF0) as i16 + al;
+        if difference < 0 {
+            difference -= 0x60;
+        }
+
+        let binary = a as i16 - b as i16 + carry as i16 - 1;
+        let signed = a as i8 as i16 - b as i8 as i16 + carry as i16 - 1;
+        (
+            difference as u8,
+            binary >= 0,
+            binary & 0x80 != 0,
+            !(-128..=127).contains(&signed),
+            binary as u8 == 0,
+        )
+    }
+
+    #[test]
+    fn decimal_arithmetic_matches_clark_reference() {
+        let flags = |registers: &Registers| {
+            (
+                registers.a,
+                registers.p.contains(StatusFlags::C),
+                registers.p.contains(StatusFlags::N),
+                registers.p.contains(StatusFlags::V),
+                registers.p.contains(StatusFlags::Z),
+            )
+        };
+        for a in 0..=255 {
+            for operand in 0..=255 {
+                for carry in [false, true] {
+                    let start = || {
+                        let mut registers = Registers {
+                            a,
+                            ..Default::default()
+                        };
+                        registers.p.insert(StatusFlags::D);
+                        registers.p.set(StatusFlags::C, carry);
+                        registers
+                    };
+
+                    let mut registers = start();
+                    ADC::<true>::execute(&mut registers, &operand);
+                    assert_eq!(
+                        flags(&registers),
+                        clark_adc(a, operand, carry),
+                        "{a:02X}+{operand:02X}+{}",
+                        carry as u8
+                    );
+
+                    let mut registers = start();
+                    SBC::<true>::execute(&mut registers, &operand);
+                    assert_eq!(
+                        flags(&registers),
+                        clark_sbc(a, operand, carry),
+                        "{a:02X}-{operand:02X}-{}",
+                        !carry as u8
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn decimal_flags_follow_nmos_quirks() {
+        // $99 + $01 wraps to $00, but N and Z come from intermediate and binary results
+        let mut registers = Registers::default();
+        registers.p.insert(StatusFlags::D);
+        registers.a = 0x99;
+        ADC::<true>::execute(&mut registers, &0x01);
+        assert_eq!(registers.a, 0x00);
+        assert!(registers.p.contains(StatusFlags::C));
+        assert!(registers.p.contains(StatusFlags::N));
+        assert!(!registers.p.contains(StatusFlags::Z));
+        assert!(!registers.p.contains(StatusFlags::V));
+    }
+
+    #[test]
+    fn decimal_mode_depends_on_cpu() {
+        // SED; CLC; LDA #$58; ADC #$46; JMP $8007
+        let program: &[(u16, &[u8])] = &[(
+            0x8000,
+            &[0xF8, 0x18, 0xA9, 0x58, 0x69, 0x46, 0x4C, 0x06, 0x80],
+        )];
+
+        let mut system = test_system::<Nmos>(program);
+        run_cpu_cycles(&mut system, 20);
+        assert_eq!(system.cpu.registers.a, 0x04);
+        assert!(system.cpu.registers.p.contains(StatusFlags::C));
+
+        let mut system = test_system::<Ricoh2A03>(program);
+        run_cpu_cycles(&mut system, 20);
+        assert_eq!(system.log().a, 0x9E);
+    }
+
+    #[test]
+    fn cmos_decimal_arithmetic_sets_valid_flags() {
+        for a in (0..100).map(to_bcd) {
+            for operand in (0..100).map(to_bcd) {
+                let mut registers = Registers::default();
+                registers.p.insert(StatusFlags::D);
+                registers.a = a;
+                cmos::ADC::execute(&mut registers, &operand);
+                let sum = from_bcd(a) + from_bcd(operand);
+                assert_eq!(registers.a, to_bcd(sum % 100), "{a:02X}+{operand:02X}");
+                assert_eq!(registers.p.contains(StatusFlags::C), sum >= 100);
+                assert_eq!(registers.p.contains(StatusFlags::Z), registers.a == 0);
+                assert_eq!(registers.p.contains(StatusFlags::N), registers.a >= 0x80);
+
+                let mut registers = Registers::default();
+                registers.p.insert(StatusFlags::D | StatusFlags::C);
+                registers.a = a;
+                cmos::SBC::execute(&mut registers, &operand);
+                let difference = from_bcd(a) as i16 - from_bcd(operand) as i16;
+                assert_eq!(
+                    registers.a,
+                    to_bcd(difference.rem_euclid(100) as u8),
+                    "{a:02X}-{operand:02X}"
+                );
+                assert_eq!(registers.p.contains(StatusFlags::C), difference >= 0);
+                assert_eq!(registers.p.contains(StatusFlags::Z), registers.a == 0);
+            }
+        }
+    }
+
+    #[test]
+    fn cmos_decimal_arithmetic_takes_a_cycle_longer() {
+        // SED or CLD; ADC #$09; SBC $10; INX
+        for (flag, cycles) in [(0xD8, 2 + 2 + 3 + 2), (0xF8, 2 + 3 + 4 + 2)] {
+            let program = [flag, 0x69, 0x09, 0xE5, 0x10, 0xE8];
+            let mut system = test_system::<Wdc65C02>(&[(0x8000, &program)]);
+            run_cpu_cycles(&mut system, 7 + cycles - 1);
+            assert_eq!(system.cpu.registers.x, 0, "{flag:02X}");
+            run_cpu_cycles(&mut system, 1);
+            assert_eq!(system.cpu.registers.x, 1, "{flag:02X}");
+
+            let mut system = test_system::<Nmos>(&[(0x8000, &program)]);
+            run_cpu_cycles(&mut system, 7 + 2 + 2 + 3 + 2);
+            assert_eq!(system.cpu.registers.x, 1, "{flag:02X}");
+        }
+    }
+
+    #[test]
+    fn cmos_indexed_shifts_skip_the_fixup_cycle() {
+        // X on the cycle before and the cycle that LDX, the op and INX should finish on after `cycles`
+        fn inx_around<V: Variant + Send + 'static>(program: &[u8], cycles: u64) -> (u8, u8) {
+            let mut system = test_system::<V>(&[(0x8000, program)]);
+            run_cpu_cycles(&mut system, 7 + cycles - 1);
+            let before = system.cpu.registers.x;
+            run_cpu_cycles(&mut system, 1);
+            (before, system.cpu.registers.x)
+        }
+
+        // LDX #$01; op $1000,X or $10FF,X; INX
+        for (opcode, low, nmos, cmos) in [
+            (0x1E, 0x00, 7, 6),
+            (0x1E, 0xFF, 7, 7),
+            (0x7E, 0x00, 7, 6),
+            (0xFE, 0x00, 7, 7),
+            (0xFE, 0xFF, 7, 7),
+        ] {
+            let program = [0xA2, 0x01, opcode, low, 0x10, 0xE8];
+            assert_eq!(
+                inx_around::<Nmos>(&program, 2 + nmos + 2),
+                (1, 2),
+                "NMOS {opcode:02X} ${low:02X}"
+            );
+            assert_eq!(
+                inx_around::<Wdc65C02>(&program, 2 + cmos + 2),
+                (1, 2),
+                "CMOS {opcode:02X} ${low:02X}"
+            );
+        }
+    }
+
+    #[test]
+    fn cmos_opcodes() {
+        let mut system = test_system::<Wdc65C02>(&[(
+            0x8000,
+            &[
+                0xA9, 0x00, // LDA #$00
+                0x85, 0x20, // STA $20
+                0xA9, 0x03, // LDA #$03
+                0x85, 0x21, // STA $21
+                0xA9, 0x0F, // LDA #$0F
+                0x85, 0x10, // STA $10
+                0x64, 0x10, // STZ $10
+                0xA9, 0x05, // LDA #$05
+                0x04, 0x10, // TSB $10
+                0xF7, 0x10, // SMB7 $10
+                0x07, 0x10, // RMB0 $10
+                0xA2, 0x22, // LDX #$22
+                0xDA, // PHX
+                0x7A, // PLY
+                0x1A, // INC A
+                0x92, 0x20, // STA ($20)
+                0xFF, 0x10, 0x02, // BBS7 $10, +2
+                0xA0, 0xFF, // LDY #$FF
+                0x80, 0xFE, // BRA -2
+            ],
+        )]);
+        run_cpu_cycles(&mut system, 200);
+        assert_eq!(system.bus.memory[0x10], 0x84);
+        assert_eq!(system.bus.memory[0x0300], 0x06);
+        assert_eq!(system.cpu.registers.a, 0x06);
+        assert_eq!(system.cpu.registers.y, 0x22);
+        assert_eq!(system.cpu.registers.pc.high(), 0x80);
+        assert!(system.cpu.registers.pc.low() >= 0x22);
+    }
+
+    #[test]
+    fn cmos_wait_and_stop() {
+        // WAI; INX; STP
+        let mut system = test_system::<Wdc65C02>(&[(0x8000, &[0xCB, 0xE8, 0xDB])]);
+        run_cpu_cycles(&mut system, 50);
+        assert_eq!(system.cpu.state(), CpuState::WaitingForInterrupt);
+
+        // A masked IRQ resumes after WAI without being serviced
+        system.bus.signals.irq = true;
+        run_cpu_cycles(&mut system, 20);
+        assert_eq!(system.cpu.registers.x, 1);
+        assert_eq!(system.cpu.state(), CpuState::Halted(0xDB));
+    }
+
+    #[test]
+    fn cmos_indirect_jmp_crosses_page() {
+        // JMP ($80FF)
+        let program: &[(u16, &[u8])] = &[(0x8000, &[0x6C, 0xFF, 0x80]), (0x80FF, &[0x00, 0x90])];
+
+        let mut system = test_system::<Nmos>(program);
+        run_cpu_cycles(&mut system, 7 + 5);
+        assert_eq!(system.cpu.registers.pc, Address(0x6C00));
+
+        let mut system = test_system::<Wdc65C02>(program);
+        run_cpu_cycles(&mut system, 7 + 6);
+        assert_eq!(system.cpu.registers.pc, Address(0x9000));
+    }
+
+    #[test]
+    fn unofficial_immediate_opcodes() {
+        let mut registers = Registers {
+            a: 0xFF,
+            ..Default::default()
+        };
+        ANC::execute(&mut registers, &0x80);
+        assert_eq!(registers.a, 0x80);
+        assert!(registers.p.contains(StatusFlags::C | StatusFlags::N));
+
+        let mut registers = Registers {
+            a: 0xFF,
+            ..Default::default()
+        };
+        registers.p.insert(StatusFlags::C);
+        ARR::<false>::execute(&mut registers, &0xFF);
+        assert_eq!(registers.a, 0xFF);
+        assert!(registers.p.contains(StatusFlags::C));
+        assert!(!registers.p.contains(StatusFlags::V));
+
+        let mut registers = Registers {
+            a: 0xF0,
+            x: 0x3C,
+            ..Default::default()
+        };
+        SBX::execute(&mut registers, &0x10);
+        assert_eq!(registers.x, 0x20);
+        assert!(registers.p.contains(StatusFlags::C));
+    }
+
+    #[test]
+    fn shx_page_cross_replaces_high_byte() {
+        // LDX #$05; LDY #$FF; SHX $02F1,Y; LDY #$01; SHX $0200,Y; JAM
+        let mut system = test_system::<Ricoh2A03>(&[(
+            0x8000,
+            &[
+                0xA2, 0x05, 0xA0, 0xFF, 0x9E, 0xF1, 0x02, 0xA0, 0x01, 0x9E, 0x00, 0x02, 0x02,
+            ],
+        )]);
+        run_cpu_cycles(&mut system, 100);
+        assert_eq!(system.bus.memory[0x01F0], 0x01);
+        assert_eq!(system.bus.memory[0x03F0], 0x00);
+        assert_eq!(system.bus.memory[0x0201], 0x01);
+        assert_eq!(system.cpu.state(), CpuState::Halted(0x02));
+    }
+
+    #[test]
+    fn soft_reset_preserves_ram() {
+        // LDA #$42; STA $10; LDX #$07; JAM
+        let mut system =
+            test_system::<Ricoh2A03>(&[(0x8000, &[0xA9, 0x42, 0x85, 0x10, 0xA2, 0x07, 0x02])]);
+        run_cpu_cycles(&mut system, 30);
+        assert_eq!(system.cpu_state(), CpuState::Halted(0x02));
+        assert_eq!(system.log().stack, 0xFD);
+
+        system.soft_reset();
+        run_cpu_cycles(&mut system, 7);
+        assert_eq!(system.cpu_state(), CpuState::Running);
+        let log = system.log();
+        assert_eq!(log.pc, Address(0x8000));
+        assert_eq!(log.stack, 0xFA);
+        assert_eq!(log.x, 0x07);
+        assert_eq!(system.bus.memory[0x10], 0x42);
+
+        system.power_on(RamInit::Zeroed);
+        run_cpu_cycles(&mut system, 7);
+        let log = system.log();
+        assert_eq!(log.stack, 0xFD);
+        assert_eq!(log.x, 0x00);
+
+        // The PPU drops its PPUDATA read buffer, which holds $AB from $2000 here
+        let rom = test_rom(&[]);
+        let mut ppu = Ppu::new(NromChrMapper::new(&rom).unwrap(), Region::Ntsc);
+        for (address, data) in [
+            (0x2006, 0x20),
+            (0x2006, 0x00),
+            (0x2007, 0xAB),
+            (0x2006, 0x20),
+            (0x2006, 0x00),
+        ] {
+            ppu.write(Address(address), data);
+        }
+        ppu.read(Address(0x2007));
+        ppu.reset();
+        assert_eq!(ppu.read(Address(0x2007)), Some(0x00));
+    }
+
+    #[test]
+    fn power_on_ram_contents() {
+        let mut ram = RamBank::<256>::new(AddressMask::from_block(Address(0), 8, 0));
+        ram.fill(RamInit::Random(1));
+        let random: Vec<u8> = (0..=255).map(|i| ram.read(Address(i)).unwrap()).collect();
+        assert!(random.iter().any(|&byte| byte != 0));
+
+        ram.fill(RamInit::Random(1));
+        assert!((0..=255).all(|i| ram.read(Address(i)) == Some(random[i as usize])));
+
+        ram.fill(RamInit::Zeroed);
+        assert!((0..=255).all(|i| ram.read(Address(i)) == Some(0)));
+    }
+
+    // NROM image with 16KiB of PRG mirrored at $8000 and $C000, using the same vectors as `test_system`
+    fn test_rom(program: &[(u16, &[u8])]) -> RomImage {
+        let mut prg_rom = vec![0u8; 16 * usize::K];
+        prg_rom[0x3FFA..].copy_from_slice(&[0x00, 0xA0, 0x00, 0x80, 0x00, 0x90]);
+        for (origin, code) in program {
+            let origin = *origin as usize & 0x3FFF;
+            prg_rom[origin..origin + code.len()].copy_from_slice(code);
+        }
+
+        RomImage {
+            prg_rom,
+            chr_rom: vec![0u8; 8 * usize::K],
+            trainer: None,
+            prg_ram_size: 0,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            mapper: 0,
+            submapper: 0,
+            nametable_layout: NametableLayout::Horizontal,
+            alternative_nametables: false,
+            region: Region::Ntsc,
+            console_type: ConsoleType::Famicom,
+            vs_ppu_type: None,
+            vs_hardware_type: None,
+            extended_console_type: None,
+            misc_rom_count: 0,
+            default_expansion_device: 0,
+        }
+    }
+
+    fn nrom_system(rom: &RomImage) -> System<RP2A03, SystemBus<NromPrgMapper, NromChrMapper>> {
+        ntsc_system(
+            NromPrgMapper::new(rom).unwrap(),
+            NromChrMapper::new(rom).unwrap(),
+        )
+    }
+
+    // LDA #data; STA address for each write
+    fn lda_sta(writes: &[(u16, u8)]) -> Vec<u8> {
+        writes
+            .iter()
+            .flat_map(|&(address, data)| [0xA9, data, 0x8D, address as u8, (address >> 8) as u8])
+            .collect()
+    }
+
+    // Each byte written to VRAM through PPUADDR and PPUDATA
+    fn ppu_writes(writes: &[(u16, u8)]) -> Vec<u8> {
+        let writes: Vec<_> = writes
+            .iter()
+            .flat_map(|&(address, data)| {
+                [
+                    (0x2006, (address >> 8) as u8),
+                    (0x2006, address as u8),
+                    (0x2007, data),
+                ]
+            })
+            .collect();
+        lda_sta(&writes)
+    }
+
+    #[test]
+    fn master_clock_drives_vblank_nmi() {
+        // LDA #$80; STA $2000; JMP $8005; NMI handler: INX, RTI
+        let rom = &test_rom(&[
+            (0x8000, &[0xA9, 0x80, 0x8D, 0x00, 0x20, 0x4C, 0x05, 0x80]),
+            (0xA000, &[0xE8, 0x40]),
+        ]);
+        let mut system = nrom_system(rom);
+
+        for _ in 0..12 * 100 {
+            system.clock_pulse();
+        }
+        assert_eq!(system.log().cycles, 100, "CPU runs every 12 master cycles");
+
+        // VBlank starts on dot 1 of scanline 241, four master cycles per dot
+        const VBLANK: u64 = (241 * 341 + 1) * 4;
+        const FRAME: u64 = 262 * 341 * 4;
+        for _ in 12 * 100..VBLANK {
+            system.clock_pulse();
+        }
+        assert_eq!(system.log().x, 0);
+
+        for _ in 0..12 * 20 {
+            system.clock_pulse();
+        }
+        assert_eq!(system.log().x, 1);
+
+        for _ in 0..FRAME {
+            system.clock_pulse();
+        }
+        assert_eq!(system.log().x, 2);
+    }
+
+    #[test]
+    fn region_frame_timing() {
+        // LDA #$80; STA $2000; JMP $8005; NMI handler: INX, RTI
+        let mut rom = test_rom(&[
+            (0x8000, &[0xA9, 0x80, 0x8D, 0x00, 0x20, 0x4C, 0x05, 0x80]),
+            (0xA000, &[0xE8, 0x40]),
+        ]);
+        rom.region = Region::Pal;
+
+        for region in [None, Some(Region::Dendy)] {
+            let mut system = system_for(
+                &rom,
+                region,
+                NromPrgMapper::new(&rom).unwrap(),
+                NromChrMapper::new(&rom).unwrap(),
+            );
+            let region = region.unwrap_or(rom.region);
+            let ppu_divisor = region.ppu_divisor();
+            let vblank = (region.vblank_scanline() as u64 * 341 + 1) * ppu_divisor;
+            let frame = region.scanlines() as u64 * 341 * ppu_divisor;
+
+            for _ in 0..vblank {
+                system.clock_pulse();
+            }
+            assert_eq!(system.log().x, 0, "{region:?}");
+
+            for _ in 0..region.cpu_divisor() * 20 {
+                system.clock_pulse();
+            }
+            assert_eq!(system.log().x, 1, "{region:?}");
+
+            for _ in 0..frame {
+                system.clock_pulse();
+            }
+            assert_eq!(system.log().x, 2, "{region:?}");
+        }
+    }
+
+    #[test]
+    fn nes2_header() {
+        let mut image = b"NES\x1a\x38\x01\x01\x09\x21\x0F\x70\x07\x01\x12\x01\x01".to_vec();
+        image.resize(16 + 16 * 1024 + 8 * 1024, 0);
+        let rom = RomImage::load(io::Cursor::new(image)).unwrap();
+
+        assert_eq!(rom.prg_rom.len(), 16 * 1024, "Exponent-multiplier PRG size");
+        assert_eq!(rom.chr_rom.len(), 8 * 1024);
+        assert_eq!(rom.mapper, 0x100);
+        assert_eq!(rom.submapper, 2);
+        assert_eq!(rom.prg_ram_size, 0);
+        assert_eq!(rom.prg_nvram_size, 8 * 1024);
+        assert_eq!(rom.chr_ram_size, 8 * 1024);
+        assert_eq!(rom.chr_nvram_size, 0);
+        assert_eq!(rom.region, Region::Pal);
+        assert_eq!(rom.console_type, ConsoleType::VsSystem);
+        assert_eq!(rom.vs_ppu_type, Some(VsPpuType::Rp2C04_0001));
+        assert_eq!(
+            rom.vs_hardware_type,
+            Some(VsHardwareType::UniSystemRbiBaseball)
+        );
+        assert_eq!(rom.extended_console_type, None);
+        assert_eq!(rom.misc_rom_count, 1);
+        assert_eq!(rom.default_expansion_device, 1);
+
+        // Console type 3 takes the extended console type from byte 13 instead
+        let mut image = b"NES\x1a\x01\x01\x00\x0B\x00\x00\x00\x00\x00\x03\x00\x00".to_vec();
+        image.resize(16 + 16 * 1024 + 8 * 1024, 0);
+        let rom = RomImage::load(io::Cursor::new(image.clone())).unwrap();
+        assert_eq!(rom.console_type, ConsoleType::Extended);
+        assert_eq!(
+            rom.extended_console_type,
+            Some(ExtendedConsoleType::DecimalFamiclone)
+        );
+        assert_eq!(rom.vs_ppu_type, None);
+
+        image[13] = 0x0F;
+        assert!(matches!(
+            RomImage::load(io::Cursor::new(image)),
+            Err(RomError::InconsistentHeader(_))
+        ));
+    }
+
+    #[test]
+    fn rom_errors() {
+        let load = |image: &[u8]| RomImage::load(io::Cursor::new(image.to_vec()));
+
+        assert!(matches!(load(b"NOT A ROM"), Err(RomError::BadMagic)));
+        assert!(matches!(
+            load(b"NES\x1a\x01"),
+            Err(RomError::TruncatedHeader)
+        ));
+
+        let mut image = b"NES\x1a\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        image.resize(16 + 100, 0);
+        assert!(matches!(
+            load(&image),
+            Err(RomError::TruncatedPrg {
+                expected: 0x4000,
+                actual: 100
+            })
+        ));
+
+        // NES 2.0 exponent-multiplier PRG size of 2^63 bytes
+        let image = b"NES\x1a\xFC\x00\x00\x08\x00\x0F\x00\x00\x00\x00\x00\x00";
+        assert!(matches!(load(image), Err(RomError::InconsistentHeader(_))));
+
+        let mut rom = test_rom(&[]);
+        rom.mapper = 4;
+        assert!(matches!(
+            mapper_from(&rom),
+            Err(RomError::UnsupportedMapper(4))
+        ));
+
+        rom.mapper = 0;
+        rom.chr_rom.truncate(100);
+        assert!(matches!(
+            NromChrMapper::new(&rom),
+            Err(RomError::InconsistentHeader(_))
+        ));
+    }
+
+    #[test]
+    fn trainer_is_mapped_at_7000() {
+        let mut image = b"NES\x1a\x01\x01\x04\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        image.extend((0..512).map(|i| i as u8));
+        image.resize(16 + 512 + 16 * 1024 + 8 * 1024, 0xEA);
+        let rom = RomImage::load(io::Cursor::new(image)).unwrap();
+        assert_eq!(rom.trainer.as_ref().map(Vec::len), Some(512));
+        assert_eq!(rom.prg_rom[0], 0xEA);
+
+        let mut prg_mapper = NromPrgMapper::new(&rom).unwrap();
+        assert_eq!(prg_mapper.read(Address(0x6FFF)), Some(0x00));
+        assert_eq!(prg_mapper.read(Address(0x7000)), Some(0x00));
+        assert_eq!(prg_mapper.read(Address(0x7001)), Some(0x01));
+        assert_eq!(prg_mapper.read(Address(0x71FF)), Some(0xFF));
+    }
+
+    #[test]
+    fn background_renders_to_framebuffer() {
+        // Backdrop $0F, palette 0 colour 1 $30, tile 1 at the top left, fine X scroll of 3, background on
+        let mut program = ppu_writes(&[(0x3F00, 0x0F), (0x3F01, 0x30), (0x2000, 0x01)]);
+        program.extend(lda_sta(&[(0x2005, 0x03), (0x2005, 0x00), (0x2001, 0x0A)]));
+        program.extend([0x4C, 0x3C, 0x80]);
+        let mut rom = test_rom(&[(0x8000, &program)]);
+        rom.chr_rom[0x10..0x18].fill(0xFF);
+        let mut system = nrom_system(&rom);
+
+        while system.bus().ppu().frame() < 2 {
+            system.clock_pulse();
+        }
+
+        let frame = system.bus().ppu().framebuffer();
+        assert_eq!(frame[0..6], [0x30, 0x30, 0x30, 0x30, 0x30, 0x0F]);
+        assert_eq!(frame[7 * 256], 0x30);
+        assert_eq!(frame[8 * 256], 0x0F);
+        assert_eq!(frame[239 * 256 + 255], 0x0F);
+    }
+
+    #[test]
+    fn sprite_zero_hit_and_overflow() {
+        // Copy OAM from $9100, draw tile 1 at the top left, enable both layers, then wait on the status flags
+        let mut program = vec![
+            0xA2, 0x00, 0xBD, 0x00, 0x91, 0x8D, 0x04, 0x20, 0xE8, 0xD0, 0xF7,
+        ];
+        program.extend(ppu_writes(&[
+            (0x3F00, 0x0F),
+            (0x3F01, 0x30),
+            (0x3F11, 0x16),
+            (0x2000, 0x01),
+        ]));
+        program.extend([0xA9, 0x00, 0x8D, 0x05, 0x20, 0x8D, 0x05, 0x20]);
+        program.extend(lda_sta(&[(0x2001, 0x1E)]));
+        // BIT $2002; BVC; INX; LDA $2002; AND #$20; BEQ; INY; JMP $8062
+        program.extend([0x2C, 0x02, 0x20, 0x50, 0xFB, 0xE8]);
+        program.extend([
+            0xAD, 0x02, 0x20, 0x29, 0x20, 0xF0, 0xF9, 0xC8, 0x4C, 0x62, 0x80,
+        ]);
+
+        // Sprite 0 at (4, 1), eight sprites on scanline 33 and a ninth that only the diagonal scan sees
+        let mut oam = [0xF0u8; 256];
+        oam[0..4].copy_from_slice(&[0x00, 0x01, 0x00, 0x04]);
+        for sprite in oam[4..36].chunks_mut(4) {
+            sprite.copy_from_slice(&[0x20, 0x01, 0x00, 0x80]);
+        }
+        oam[40..44].copy_from_slice(&[0xF0, 0x20, 0x00, 0x00]);
+
+        let mut rom = test_rom(&[(0x8000, &program), (0x9100, &oam)]);
+        rom.chr_rom[0x10..0x18].fill(0xFF);
+        let mut system = nrom_system(&rom);
+
+        while system.bus().ppu().frame() < 2 {
+            system.clock_pulse();
+        }
+
+        assert_eq!(system.log().x, 1, "sprite 0 hit");
+        assert_eq!(system.log().y, 1, "sprite overflow");
+        let frame = system.bus().ppu().framebuffer();
+        assert_eq!(frame[4], 0x30);
+        assert_eq!(
+            frame[256 + 3..256 + 13],
+            [0x30, 0x16, 0x16, 0x16, 0x16, 0x16, 0x16, 0x16, 0x16, 0x0F]
+        );
+        assert_eq!(frame[33 * 256 + 0x80], 0x16);
+    }
+
+    #[test]
+    fn scroll_registers_share_temp_address() {
+        // Tile 1 at $2401, then scroll to coarse X 1 of nametable 1 through $2005 after a $2002 read resets w
+        let mut program = ppu_writes(&[
+            (0x2401, 0x01),
+            (0x3F00, 0x0F),
+            (0x3F01, 0x30),
+            (0x2000, 0x00),
+        ]);
+        program.extend([0xA9, 0x55, 0x8D, 0x05, 0x20, 0xAD, 0x02, 0x20]);
+        program.extend(lda_sta(&[
+            (0x2005, 0x08),
+            (0x2005, 0x00),
+            (0x2000, 0x01),
+            (0x2001, 0x0A),
+        ]));
+        program.extend([0x4C, 0x58, 0x80]);
+        let mut rom = test_rom(&[(0x8000, &program)]);
+        rom.chr_rom[0x10..0x18].fill(0xFF);
+        let mut system = nrom_system(&rom);
+
+        while system.bus().ppu().frame() < 2 {
+            system.clock_pulse();
+        }
+
+        let frame = system.bus().ppu().framebuffer();
+        assert_eq!(frame[0..8], [0x30; 8]);
+        assert_eq!(frame[8], 0x0F);
+    }
+
+    #[test]
+    fn ppudata_buffer_increment_and_palette() {
+        // +32 increment: $AB to $2000 and $CD to $2020, $2A to $3F10, then read them back through PPUDATA
+        let mut program = lda_sta(&[(0x2000, 0x04), (0x2006, 0x20), (0x2006, 0x00)]);
+        program.extend(lda_sta(&[(0x2007, 0xAB), (0x2007, 0xCD)]));
+        program.extend(ppu_writes(&[(0x3F10, 0x2A)]));
+        program.extend(lda_sta(&[(0x2006, 0x20), (0x2006, 0x00)]));
+        // LDA $2007 (stale buffer); LDX $2007; LDY $2007
+        program.extend([0xAD, 0x07, 0x20, 0xAE, 0x07, 0x20, 0xAC, 0x07, 0x20]);
+        program.extend(lda_sta(&[(0x2006, 0x3F), (0x2006, 0x00)]));
+        program.extend([0xAD, 0x07, 0x20]);
+        let end = 0x8000 + program.len() as u16;
+        program.extend([0x4C, end as u8, (end >> 8) as u8]);
+        let rom = test_rom(&[(0x8000, &program)]);
+        let mut system = nrom_system(&rom);
+
+        for _ in 0..12 * 200 {
+            system.clock_pulse();
+        }
+
+        let log = system.log();
+        assert_eq!(log.pc, Address(end));
+        assert_eq!(
+            (log.x, log.y),
+            (0xAB, 0xCD),
+            "buffered reads with +32 increment"
+        );
+        assert_eq!(log.a, 0x2A, "$3F00 mirrors $3F10 and is read unbuffered");
+    }
+
+    #[test]
+    fn nametable_arrangements() {
+        // $AA to $2000, $BB to $2400, $CC to $2800, then read $2C00 back through the buffer into X
+        let mut program = ppu_writes(&[(0x2000, 0xAA), (0x2400, 0xBB), (0x2800, 0xCC)]);
+        program.extend(lda_sta(&[(0x2006, 0x2C), (0x2006, 0x00)]));
+        program.extend([0xAD, 0x07, 0x20, 0xAE, 0x07, 0x20]);
+        let end = 0x8000 + program.len() as u16;
+        program.extend([0x4C, end as u8, (end >> 8) as u8]);
+
+        for (layout, alternative_nametables, expected) in [
+            (NametableLayout::Horizontal, false, 0xBB),
+            (NametableLayout::Vertical, false, 0xCC),
+            (NametableLayout::Vertical, true, 0x00),
+        ] {
+            let mut rom = test_rom(&[(0x8000, &program)]);
+            rom.nametable_layout = layout;
+            rom.alternative_nametables = alternative_nametables;
+            let mut system = nrom_system(&rom);
+
+            for _ in 0..12 * 200 {
+                system.clock_pulse();
+            }
+
+            assert_eq!(system.log().x, expected, "{layout:?}");
+        }
+    }
+
+    #[test]
+    fn axrom_switches_nametable_mid_frame() {
+        // Page B gets tile 1 down its first column through +32 increments, page A stays blank
+        let mut program = lda_sta(&[
+            (0x8000, 0x10),
+            (0x2000, 0x04),
+            (0x2006, 0x20),
+            (0x2006, 0x00),
+        ]);
+        // LDX #30; LDA #$01; STA $2007; DEX; BNE -6
+        program.extend([0xA2, 0x1E, 0xA9, 0x01, 0x8D, 0x07, 0x20, 0xCA, 0xD0, 0xFA]);
+        program.extend(ppu_writes(&[(0x3F00, 0x0F), (0x3F01, 0x30)]));
+        program.extend(lda_sta(&[
+            (0x2000, 0x00),
+            (0x2005, 0x00),
+            (0x2005, 0x00),
+            (0x2001, 0x0A),
+        ]));
+        // Each frame: BIT $2002; BPL -5, select page A, wait about 120 scanlines, then select page B
+        let frame = 0x8000 + program.len() as u16;
+        program.extend([0x2C, 0x02, 0x20, 0x10, 0xFB]);
+        program.extend(lda_sta(&[(0x8000, 0x00)]));
+        // LDY #12; LDX #0; DEX; BNE -3; DEY; BNE -8
+        program.extend([0xA0, 0x0C, 0xA2, 0x00, 0xCA, 0xD0, 0xFD, 0x88, 0xD0, 0xF8]);
+        program.extend(lda_sta(&[(0x8000, 0x10)]));
+        program.extend([0x4C, frame as u8, (frame >> 8) as u8]);
+
+        let mut rom = test_rom(&[(0x8000, &program)]);
+        rom.mapper = 7;
+        rom.prg_rom = rom.prg_rom.repeat(2);
+        rom.chr_rom[0x10..0x18].fill(0xFF);
+        let (prg_mapper, chr_mapper) = mapper_from(&rom).unwrap();
+        let mut system = ntsc_system(prg_mapper, chr_mapper);
+
+        while system.bus().ppu().frame() < 3 {
+            system.clock_pulse();
+        }
+
+        let frame = system.bus().ppu().framebuffer();
+        assert_eq!(frame[8 * 256], 0x0F, "page A above the switch");
+        assert_eq!(frame[232 * 256], 0x30, "page B below it");
+    }
+
+    #[test]
+    fn oam_dma_halts_cpu() {
+        // An optional LDA $00 shifts the parity, then LDA #$90; STA $4014; LDA #$05; STA $2003; LDX $2004
+        let mut oam = [0u8; 256];
+        oam.iter_mut()
+            .enumerate()
+            .for_each(|(i, byte)| *byte = i as u8 ^ 0x5A);
+        let mut stalls = vec![];
+        for prefix in [&[][..], &[0xA5, 0x00]] {
+            let mut program = prefix.to_vec();
+            program.extend(lda_sta(&[(0x4014, 0x90), (0x2003, 0x05)]));
+            program.extend([0xAE, 0x04, 0x20]);
+            let end = 0x8000 + program.len() as u16;
+            program.extend([0x4C, end as u8, (end >> 8) as u8]);
+            let rom = test_rom(&[(0x8000, &program), (0x9000, &oam)]);
+            let mut system = nrom_system(&rom);
+
+            let mut halted_pulses = 0;
+            for _ in 0..12 * 1000 {
+                system.clock_pulse();
+                if system.cpu_state() == CpuState::WaitingForDma {
+                    halted_pulses += 1;
+                }
+            }
+
+            assert_eq!(system.log().x, 0x05 ^ 0x5A);
+            stalls.push(halted_pulses / 12);
+        }
+        stalls.sort();
+        assert_eq!(stalls, [513, 514]);
+    }
+
+    #[test]
+    fn dmc_dma_steals_cycles_and_repeats_reads() {
+        let run = |mut program: Vec<u8>| {
+            let end = 0x8000 + program.len() as u16;
+            program.extend([0x4C, end as u8, (end >> 8) as u8]);
+            let rom = test_rom(&[(0x8000, &program), (0xC400, &[0xFF])]);
+            let mut system = nrom_system(&rom);
+            let mut halted_pulses = 0;
+            for _ in 0..12 * 4000 {
+                system.clock_pulse();
+                if system.cpu_state() == CpuState::WaitingForDma {
+                    halted_pulses += 1;
+                }
+            }
+            (system, halted_pulses / 12)
+        };
+
+        // A one byte sample from $C400 with its IRQ enabled, fetched over a halt, dummy and read cycle
+        let sample = [
+            (0x4010, 0x8F),
+            (0x4011, 0x00),
+            (0x4012, 0x10),
+            (0x4013, 0x00),
+        ];
+        let (mut system, stall) = run(lda_sta(&[&sample[..], &[(0x4015, 0x10)]].concat()));
+        assert_eq!(stall, 3);
+        // Eight set bits raise the level by 2 each, and the IRQ flag outlives the finished sample
+        assert_eq!(system.bus().apu().dmc().output(), 16);
+        assert_eq!(system.bus.read(Apu::STATUS) & 0x90, 0x80);
+
+        // Fill $2000-$20FF with its own low byte, then loop the sample under 200 LDA $2007 and one LDX $2007, with
+        // an optional LDA $00 to shift the parity. X counts the earlier $2007 reads, one per LDA unless a fetch
+        // halts the CPU on the read itself.
+        let mut reads = [&[][..], &[0xA5, 0x00]].map(|prefix| {
+            let mut program = prefix.to_vec();
+            program.extend(lda_sta(&[(0x2006, 0x20), (0x2006, 0x00)]));
+            program.extend([0xA2, 0x00, 0x8E, 0x07, 0x20, 0xE8, 0xD0, 0xFA]);
+            program.extend(lda_sta(&[
+                (0x4010, 0x4F),
+                (0x4012, 0x10),
+                (0x2006, 0x20),
+                (0x2006, 0x00),
+                (0x4015, 0x10),
+            ]));
+            program.extend([0xAD, 0x07, 0x20].repeat(200));
+            program.extend([0xAE, 0x07, 0x20]);
+            run(program).0.log().x
+        });
+        // One parity lands a fetch on a read, repeating it on the halt and dummy cycles
+        reads.sort();
+        assert_eq!(reads, [199, 201]);
+    }
+
+    #[test]
+    fn framebuffer_holds_the_last_completed_frame() {
+        let rom = test_rom(&[]);
+        let mut ppu = Ppu::new(NromChrMapper::new(&rom).unwrap(), Region::Ntsc);
+        let run_until = |ppu: &mut Ppu<_>, frame: u64, scanline: usize| {
+            while ppu.frame() < frame {
+                ppu.tick();
+            }
+            (0..scanline * 341).for_each(|_| ppu.tick());
+        };
+
+        // Backdrop $00 for the first frame, then $0F from the second, checked partway through drawing it
+        run_until(&mut ppu, 1, 0);
+        for (address, data) in [
+            (0x2006, 0x3F),
+            (0x2006, 0x00),
+            (0x2007, 0x0F),
+            (0x2006, 0x00),
+            (0x2006, 0x00),
+        ] {
+            ppu.write(Address(address), data);
+        }
+        run_until(&mut ppu, 1, 22 + 100);
+        assert!(ppu.framebuffer().iter().all(|&pixel| pixel == 0x00));
+
+        run_until(&mut ppu, 2, 0);
+        assert!(ppu.framebuffer().iter().all(|&pixel| pixel == 0x0F));
+    }
+
+    #[test]
+    fn vblank_read_race() {
+        const STATUS: Address = Address(0x2002);
+        const FRAME: usize = 262 * 341;
+        let rom = test_rom(&[]);
+        let mut ppu = Ppu::new(NromChrMapper::new(&rom).unwrap(), Region::Ntsc);
+        ppu.write(Address(0x2000), 0x80);
+
+        // Scanline 241 starts 241 * 341 dots in, and the flag is set on its dot 1
+        let run_to = |ppu: &mut Ppu<_>, dot: usize| {
+            let mut nmi = false;
+            for _ in 0..dot {
+                ppu.tick();
+                nmi |= ppu.nmi();
+            }
+            nmi
+        };
+
+        // One dot early: reads clear and the flag is never set
+        assert!(!run_to(&mut ppu, 241 * 341));
+        assert_eq!(ppu.read(STATUS).unwrap() & 0x80, 0);
+        assert!(!run_to(&mut ppu, 20));
+        assert_eq!(ppu.read(STATUS).unwrap() & 0x80, 0);
+
+        // On the dot it is set: reads set, but the NMI is suppressed
+        run_to(&mut ppu, FRAME - 20);
+        assert!(!run_to(&mut ppu, 1));
+        assert_eq!(ppu.read(STATUS).unwrap() & 0x80, 0x80);
+        assert!(!run_to(&mut ppu, 20));
+
+        // Two dots later the NMI has already gone out
+        run_to(&mut ppu, FRAME - 21);
+        assert!(run_to(&mut ppu, 3));
+        assert_eq!(ppu.read(STATUS).unwrap() & 0x80, 0x80);
+
+        // Enabling NMI during vertical blank raises it again
+        run_to(&mut ppu, FRAME - 3);
+        ppu.write(Address(0x2000), 0x00);
+        assert!(!run_to(&mut ppu, 10));
+        ppu.write(Address(0x2000), 0x80);
+        assert!(run_to(&mut ppu, 3));
+    }
+
+    #[test]
+    fn palettes() {
+        let mut pal = [0u8; 192];
+        pal[0x16 * 3..0x17 * 3].copy_from_slice(&[200, 100, 50]);
+        let palette = Palette::load(&pal[..]).unwrap();
+        assert_eq!(palette.rgb(0x16), [200, 100, 50]);
+        assert_eq!(
+            palette.rgb(0x16 | 0x40),
+            [200, 75, 37],
+            "red emphasis dims green and blue"
+        );
+        assert!(matches!(
+            Palette::from_bytes(&[0; 100]),
+            Err(PaletteError::BadSize(100))
+        ));
+
+        let ntsc = Palette::default();
+        assert_eq!(ntsc.rgb(0x0F), [0, 0, 0]);
+        let brightness = |rgb: [u8; 3]| rgb.iter().map(|&c| c as u32).sum::<u32>();
+        assert!(brightness(ntsc.rgb(0x30)) > brightness(ntsc.rgb(0x10)));
+        assert!(brightness(ntsc.rgb(0x30 | 0x1C0)) < brightness(ntsc.rgb(0x30)));
+        let (red, blue) = (ntsc.rgb(0x16), ntsc.rgb(0x12));
+        assert!(red[0] > red[2] && blue[2] > blue[0]);
+
+        let rgb_ppu = Palette::rgb_ppu(VsPpuType::Rc2C05_01);
+        assert_eq!(rgb_ppu.rgb(0x20), [255, 255, 255]);
+        assert_eq!(
+            rgb_ppu.rgb(0x0D | 0x40),
+            [255, 0, 0],
+            "emphasis turns the channel fully on"
+        );
+        assert_eq!(
+            Palette::rgb_ppu(VsPpuType::Rp2C04_0001).rgb(0x08),
+            [255, 255, 255]
+        );
+
+        // Greyscale also applies to palette reads through PPUDATA
+        let rom = test_rom(&[]);
+        let mut ppu = Ppu::new(NromChrMapper::new(&rom).unwrap(), Region::Ntsc);
+        for (register, data) in [
+            (6, 0x3F),
+            (6, 0x01),
+            (7, 0x16),
+            (6, 0x3F),
+            (6, 0x01),
+            (1, 0x01),
+        ] {
+            ppu.write(Address(0x2000 + register), data);
+        }
+        assert_eq!(ppu.read(Address(0x2007)).unwrap(), 0x10);
+    }
+
+    #[test]
+    fn ntsc_filter() {
+        let filter = NtscFilter::new(602);
+        let palette = Palette::default();
+        let close = |a: [u8; 3], b: [u8; 3]| a.iter().zip(b).all(|(&a, b)| a.abs_diff(b) <= 2);
+
+        let solid = Box::new([0x16u16; 256 * 240]);
+        let output = filter.filter(&solid, 0);
+        assert_eq!(output.len(), 602 * 240);
+        assert!(close(output[100 * 602 + 300], palette.rgb(0x16)));
+
+        // The last columns are neither darkened nor tinted
+        assert!(close(output[100 * 602 + 601], palette.rgb(0x16)));
+        let grey = Box::new([0x10u16; 256 * 240]);
+        let output = filter.filter(&grey, 0);
+        assert!(close(output[100 * 602 + 601], palette.rgb(0x10)));
+
+        // Stripes one dot wide produce artefact colours that move with the subcarrier phase
+        let mut stripes = Box::new([0x0Fu16; 256 * 240]);
+        stripes
+            .iter_mut()
+            .step_by(2)
+            .for_each(|pixel| *pixel = 0x30);
+        let frames = [0, 1, 3].map(|frame| filter.filter(&stripes, frame));
+        assert_ne!(frames[0], frames[1]);
+        assert_eq!(frames[0], frames[2]);
+        assert!(frames[0].iter().any(|&[r, g, b]| r != g || g != b));
+    }
+
+    #[test]
+    fn ppu_io_latch_decays() {
+        let rom = test_rom(&[]);
+        let mut ppu = Ppu::new(NromChrMapper::new(&rom).unwrap(), Region::Ntsc);
+        let decay = Region::Ntsc.master_clock_rate() * 6 / 10 / Region::Ntsc.ppu_divisor();
+        let run = |ppu: &mut Ppu<_>, dots: u64| (0..dots).for_each(|_| ppu.tick());
+
+        // Palette entry 0 is $3F, PPUADDR points at it and the latch holds $C0
+        for (register, data) in [
+            (6, 0x3F),
+            (6, 0x00),
+            (7, 0x3F),
+            (6, 0x3F),
+            (6, 0x00),
+            (2, 0xC0),
+        ] {
+            ppu.write(Address(0x2000 + register), data);
+        }
+        assert_eq!(ppu.read(Address(0x2000)).unwrap(), 0xC0);
+
+        // Palette reads only drive the low six bits, leaving the top two to decay
+        run(&mut ppu, decay * 7 / 10);
+        assert_eq!(ppu.read(Address(0x2007)).unwrap(), 0xFF);
+        run(&mut ppu, decay / 2);
+        assert_eq!(ppu.read(Address(0x2005)).unwrap(), 0x3F);
+        assert_eq!(ppu.read(Address(0x2002)).unwrap() & 0x1F, 0x1F);
+        run(&mut ppu, decay);
+        assert_eq!(ppu.read(Address(0x2000)).unwrap(), 0x00);
+    }
+
+    #[test]
+    fn pulse_channels_sweep_and_sequence() {
+        let mut pulses = [PulseChannel::One, PulseChannel::Two].map(|channel| {
+            let mut pulse = Pulse::new(channel);
+            pulse.set_enabled(true);
+            // 50% duty, halted, constant volume 5, sweeping down by half every half frame
+            for (register, data) in [(0, 0b1011_0101), (1, 0b1000_1001), (2, 0x00), (3, 0x09)] {
+                pulse.write(register, data);
+            }
+            pulse
+        });
+
+        let steps: Vec<u8> = (0..8)
+            .map(|_| {
+                (0..=0x100).for_each(|_| pulses[0].tick());
+                pulses[0].output()
+            })
+            .collect();
+        assert_eq!(steps, [5, 5, 5, 5, 0, 0, 0, 0]);
+
+        pulses.iter_mut().for_each(Pulse::half_frame);
+        assert_eq!(pulses.each_ref().map(Pulse::timer_period), [0x7F, 0x80]);
+
+        // A target period past $7FF mutes the channel even with the sweep disabled
+        let pulse = &mut pulses[1];
+        pulse.write(1, 0x00);
+        pulse.write(3, 0x04);
+        assert!((0..16).all(|_| {
+            pulse.tick();
+            pulse.output() == 0
+        }));
+        assert!(pulse.is_active());
+    }
+
+    #[test]
+    fn apu_status_triangle_and_noise() {
+        let mut apu = Apu::new(&NTSC_RATES);
+        let write = |apu: &mut Apu, address, data| apu.write(Address(address), data);
+
+        // Lengths only load into enabled channels, and disabling clears them
+        write(&mut apu, 0x4003, 0x08);
+        assert_eq!(apu.read(Apu::STATUS), Some(0x00));
+        write(&mut apu, 0x4015, 0x0F);
+        for address in [0x4003, 0x4007, 0x400B, 0x400F] {
+            write(&mut apu, address, 0x08);
+        }
+        assert_eq!(apu.read(Apu::STATUS), Some(0x0F));
+        write(&mut apu, 0x4015, 0x0A);
+        assert_eq!(apu.read(Apu::STATUS), Some(0x0A));
+        assert_eq!(apu.read(Address(0x4000)), None);
+
+        // The triangle only steps while the linear counter is loaded, and freezes where it stopped
+        write(&mut apu, 0x4015, 0x0F);
+        write(&mut apu, 0x4008, 0x81);
+        write(&mut apu, 0x400A, 0x00);
+        write(&mut apu, 0x400B, 0x08);
+        apu.quarter_frame();
+        let steps: Vec<u8> = (0..4)
+            .map(|_| {
+                apu.tick();
+                apu.triangle().output()
+            })
+            .collect();
+        assert_eq!(steps, [14, 13, 12, 11]);
+        write(&mut apu, 0x4008, 0x00);
+        apu.quarter_frame();
+        apu.tick();
+        assert_eq!(apu.triangle().output(), 11);
+
+        // Short mode repeats within 93 steps, long mode doesn't
+        write(&mut apu, 0x400C, 0x31);
+        let samples = |apu: &mut Apu, mode: u8| -> Vec<u8> {
+            write(apu, 0x400E, mode);
+            (0..186)
+                .map(|_| {
+                    (0..4).for_each(|_| apu.tick());
+                    apu.noise().output()
+                })
+                .collect()
+        };
+        let short = samples(&mut apu, 0x80);
+        assert!(short.contains(&1) && short.contains(&0));
+        assert_eq!(short[..93], short[93..]);
+        let long = samples(&mut apu, 0x00);
+        assert_ne!(long[..93], long[93..]);
+    }
+
+    #[test]
+    fn apu_frame_counter() {
+        let mut apu = Apu::new(&NTSC_RATES);
+        let cycles_until_irq = |apu: &mut Apu| {
+            (1..40000).find(|_| {
+                apu.tick();
+                apu.irq()
+            })
+        };
+        let acknowledge = |apu: &mut Apu| {
+            (0..2).for_each(|_| apu.tick());
+            assert_eq!(apu.read(Apu::STATUS).unwrap() & 0x40, 0x40);
+            assert!(!apu.irq());
+        };
+
+        // Power on behaves as a $4017 write of $00 on a get cycle
+        assert_eq!(cycles_until_irq(&mut apu), Some(3 + 29828));
+        acknowledge(&mut apu);
+
+        // Writes on put cycles take a cycle longer to restart the sequence
+        let mut restarts = vec![];
+        for _ in 0..2 {
+            apu.write(Address(0x4017), 0x00);
+            restarts.push(cycles_until_irq(&mut apu).unwrap());
+            acknowledge(&mut apu);
+        }
+        assert_eq!(restarts, [3 + 29828, 4 + 29828]);
+
+        apu.write(Address(0x4017), 0x40);
+        assert_eq!(cycles_until_irq(&mut apu), None);
+
+        // The 5-step sequence clocks the length counters as it starts and never interrupts
+        apu.write(Address(0x4015), 0x01);
+        apu.write(Address(0x4000), 0x00);
+        apu.write(Address(0x4003), 0x18);
+        apu.write(Address(0x4017), 0x80);
+        (0..4).for_each(|_| apu.tick());
+        assert_eq!(apu.read(Apu::STATUS), Some(0x01));
+        assert_eq!(cycles_until_irq(&mut apu), None);
+        assert_eq!(apu.read(Apu::STATUS), Some(0x00));
+
+        // Reset clears every length counter, the triangle's included
+        apu.write(Address(0x4015), 0x0F);
+        for address in [0x4003, 0x4007, 0x400B, 0x400F] {
+            apu.write(Address(address), 0x08);
+        }
+        assert_eq!(apu.read(Apu::STATUS), Some(0x0F));
+        apu.reset();
+        assert_eq!(apu.read(Apu::STATUS), Some(0x00));
+    }
+
+    #[test]
+    fn mixer_resamples_apu_output() {
+        // Pulse 1 at full volume, 50% duty and about 440Hz, held by its length counter halt
+        let mut apu = Apu::new(&NTSC_RATES);
+        for (address, data) in [
+            (0x4015, 0x01),
+            (0x4000, 0xBF),
+            (0x4002, 0xFD),
+            (0x4003, 0x00),
+        ] {
+            apu.write(Address(address), data);
+        }
+        let mut mono = Mixer::new(Region::Ntsc, 48_000);
+        let panning = Panning {
+            pulse1: -1.0,
+            ..Default::default()
+        };
+        let mut stereo = Mixer::with_panning(Region::Ntsc, 44_100, panning);
+        assert_eq!((mono.channels(), stereo.channels()), (1, 2));
+
+        const FRAME_CYCLES: usize = 29781;
+        let (mut mono_samples, mut stereo_samples) = (vec![], vec![]);
+        for _ in 0..10 {
+            for _ in 0..FRAME_CYCLES {
+                apu.tick();
+                mono.clock(&apu);
+                stereo.clock(&apu);
+            }
+            mono_samples.push(mono.end_frame_f32());
+            stereo_samples.push(stereo.end_frame_i16());
+        }
+
+        let cpu_rate = Region::Ntsc.master_clock_rate() as f64 / Region::Ntsc.cpu_divisor() as f64;
+        let expected = (10 * FRAME_CYCLES) as f64 * 48_000.0 / cpu_rate;
+        let produced = mono_samples.iter().map(Vec::len).sum::<usize>();
+        assert!((produced as f64 - expected).abs() < 1.0);
+
+        // Once the high-pass filters settle the square swings evenly about zero. Each edge is a step of about 0.149,
+        // the pulse DAC's level for 15, but the 440Hz filter drains most of it before the next one
+        let settled = mono_samples[5..].concat();
+        let peak = settled
+            .iter()
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        assert!((0.1..0.149).contains(&peak), "peak {peak}");
+        let mean = settled.iter().sum::<f32>() / settled.len() as f32;
+        assert!(mean.abs() < 0.01, "mean {mean}");
+
+        // Hard left panning leaves the right channel silent, once the idle triangle's DC level has drained
+        let settled = stereo_samples[5..].concat();
+        assert!(settled.iter().step_by(2).any(|&sample| sample != 0));
+        assert!(settled.iter().skip(1).step_by(2).all(|&sample| sample == 0));
+    }
+
     #[bench]
     fn performance_benchmark(b: &mut test::Bencher) {
         let nestest = &load_nestest();
@@ -493,7 +2100,7 @@ mod tests {
         const CYCLE_TARGET: u32 = 26554;
         b.bytes = CYCLE_TARGET as u64;
         b.iter(|| {
-            let (prg_mapper, chr_mapper) = mapper_from(nestest);
+            let (prg_mapper, chr_mapper) = mapper_from(nestest).unwrap();
             let mut system = ntsc_system(prg_mapper, chr_mapper);
             for _ in 0..CYCLE_TARGET {
                 system.clock_pulse();